@@ -0,0 +1,176 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ *
+ * (c) ZeroTier, Inc.
+ * https://www.zerotier.com/
+ */
+
+const RFC4648_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+const CROCKFORD_ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const PAD: u8 = b'=';
+const INVALID: u8 = 0xff;
+
+const fn decode_table(alphabet: &[u8; 32], crockford: bool) -> [u8; 256] {
+    let mut t = [INVALID; 256];
+    let mut i = 0;
+    while i < 32 {
+        t[alphabet[i] as usize] = i as u8;
+        if crockford {
+            t[alphabet[i].to_ascii_lowercase() as usize] = i as u8;
+        }
+        i += 1;
+    }
+    if crockford {
+        // Letters that are easily confused with digits decode as those digits.
+        t[b'O' as usize] = 0;
+        t[b'o' as usize] = 0;
+        t[b'I' as usize] = 1;
+        t[b'i' as usize] = 1;
+        t[b'L' as usize] = 1;
+        t[b'l' as usize] = 1;
+    }
+    t
+}
+
+static RFC4648_DECODE: [u8; 256] = decode_table(RFC4648_ALPHABET, false);
+static CROCKFORD_DECODE: [u8; 256] = decode_table(CROCKFORD_ALPHABET, true);
+
+fn encode(b: &[u8], alphabet: &[u8; 32], pad: bool) -> String {
+    let mut s = String::with_capacity(b.len().div_ceil(5) * 8);
+    for chunk in b.chunks(5) {
+        let mut block = [0_u8; 5];
+        block[..chunk.len()].copy_from_slice(chunk);
+        let v = u64::from_be_bytes([0, 0, 0, block[0], block[1], block[2], block[3], block[4]]);
+        let chars = (chunk.len() * 8).div_ceil(5);
+        for i in 0..chars {
+            s.push(alphabet[((v >> (35 - i * 5)) & 0x1f) as usize] as char);
+        }
+        if pad {
+            for _ in chars..8 {
+                s.push(PAD as char);
+            }
+        }
+    }
+    s
+}
+
+fn decode(s: &[u8], table: &[u8; 256]) -> Option<Vec<u8>> {
+    let mut b = Vec::with_capacity(s.len() * 5 / 8);
+    for chunk in s.chunks(8) {
+        // Partial blocks can only end on a byte boundary with no leftover bits set.
+        let bytes = match chunk.len() {
+            8 => 5,
+            7 => 4,
+            5 => 3,
+            4 => 2,
+            2 => 1,
+            _ => return None,
+        };
+        let mut v = 0_u64;
+        for c in chunk {
+            let x = table[*c as usize];
+            if x == INVALID {
+                return None;
+            }
+            v = (v << 5) | (x as u64);
+        }
+        let unused_bits = chunk.len() * 5 - bytes * 8;
+        if (v & ((1 << unused_bits) - 1)) != 0 {
+            return None;
+        }
+        v >>= unused_bits;
+        for i in (0..bytes).rev() {
+            b.push((v >> (i * 8)) as u8);
+        }
+    }
+    Some(b)
+}
+
+/// Encode a byte slice as RFC 4648 base32 with padding.
+#[inline(always)]
+pub fn to_string(b: &[u8]) -> String {
+    encode(b, RFC4648_ALPHABET, true)
+}
+
+/// Encode a byte slice as RFC 4648 base32 without padding.
+#[inline(always)]
+pub fn to_string_unpadded(b: &[u8]) -> String {
+    encode(b, RFC4648_ALPHABET, false)
+}
+
+/// Decode RFC 4648 base32 with or without padding.
+///
+/// Only uppercase is accepted, and if padding is present it must be complete.
+pub fn from_string(s: &str) -> Option<Vec<u8>> {
+    let s = s.as_bytes();
+    let unpadded = s.iter().rposition(|c| *c != PAD).map_or(0, |i| i + 1);
+    if unpadded < s.len() && (!s.len().is_multiple_of(8) || (s.len() - unpadded) >= 8) {
+        return None;
+    }
+    decode(&s[..unpadded], &RFC4648_DECODE)
+}
+
+/// Encode a byte slice with Crockford's base32 alphabet, which omits I, L, O and U.
+#[inline(always)]
+pub fn to_string_crockford(b: &[u8]) -> String {
+    encode(b, CROCKFORD_ALPHABET, false)
+}
+
+/// Decode Crockford's base32, ignoring case and hyphens and reading I and L as 1 and O as 0.
+pub fn from_string_crockford(s: &str) -> Option<Vec<u8>> {
+    let s: Vec<u8> = s.bytes().filter(|c| *c != b'-').collect();
+    decode(&s, &CROCKFORD_DECODE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rfc4648_vectors() {
+        for (plain, encoded) in [
+            ("", ""),
+            ("f", "MY======"),
+            ("fo", "MZXQ===="),
+            ("foo", "MZXW6==="),
+            ("foob", "MZXW6YQ="),
+            ("fooba", "MZXW6YTB"),
+            ("foobar", "MZXW6YTBOI======"),
+        ] {
+            assert_eq!(to_string(plain.as_bytes()), encoded);
+            assert_eq!(from_string(encoded).unwrap(), plain.as_bytes());
+            let unpadded = encoded.trim_end_matches('=');
+            assert_eq!(to_string_unpadded(plain.as_bytes()), unpadded);
+            assert_eq!(from_string(unpadded).unwrap(), plain.as_bytes());
+        }
+        assert!(from_string("MZXW6===").is_some());
+        assert!(from_string("MZXW6==").is_none());
+        assert!(from_string("mzxw6").is_none());
+        assert!(from_string("MZXW7").is_none());
+        assert!(from_string("M").is_none());
+        assert!(from_string("MZ=XW6==").is_none());
+        assert!(from_string("========").is_none());
+    }
+
+    #[test]
+    fn crockford() {
+        let data: Vec<u8> = (0..=255).collect();
+        for len in 0..40 {
+            let s = to_string_crockford(&data[len..len * 2]);
+            assert!(!s.contains(['I', 'L', 'O', 'U']));
+            assert_eq!(from_string_crockford(&s).unwrap(), &data[len..len * 2]);
+            assert_eq!(
+                from_string_crockford(&s.to_ascii_lowercase()).unwrap(),
+                &data[len..len * 2]
+            );
+        }
+        assert_eq!(to_string_crockford(&[0x08, 0x42, 0x10, 0x84, 0x21]), "11111111");
+        assert_eq!(
+            from_string_crockford("1iIl-Ll11").unwrap(),
+            [0x08, 0x42, 0x10, 0x84, 0x21]
+        );
+        assert_eq!(from_string_crockford("oO000000").unwrap(), [0; 5]);
+        assert!(from_string_crockford("UUUUUUUU").is_none());
+    }
+}
@@ -0,0 +1,153 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ *
+ * (c) ZeroTier, Inc.
+ * https://www.zerotier.com/
+ */
+
+use sha2::{Digest, Sha256};
+
+const ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+const INVALID: u8 = 0xff;
+
+/// Length of the checksum appended by to_string_check().
+pub const CHECKSUM_SIZE: usize = 4;
+
+static DECODE: [u8; 256] = {
+    let mut t = [INVALID; 256];
+    let mut i = 0;
+    while i < 58 {
+        t[ALPHABET[i] as usize] = i as u8;
+        i += 1;
+    }
+    t
+};
+
+/// Encode a byte slice as Base58 using the Bitcoin alphabet.
+///
+/// Each leading zero byte becomes a leading '1'. Encoding is quadratic in the input length, so this
+/// is meant for short values like keys and addresses.
+pub fn to_string(b: &[u8]) -> String {
+    let zeroes = b.iter().take_while(|b| **b == 0).count();
+    // Digits are stored least significant first.
+    let mut digits: Vec<u8> = Vec::with_capacity((b.len() - zeroes) * 138 / 100 + 1);
+    for byte in &b[zeroes..] {
+        let mut carry = *byte as u32;
+        for d in digits.iter_mut() {
+            carry += (*d as u32) << 8;
+            *d = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+    let mut s = String::with_capacity(zeroes + digits.len());
+    for _ in 0..zeroes {
+        s.push('1');
+    }
+    for d in digits.iter().rev() {
+        s.push(ALPHABET[*d as usize] as char);
+    }
+    s
+}
+
+/// Decode Base58 using the Bitcoin alphabet.
+pub fn from_string(s: &str) -> Option<Vec<u8>> {
+    let s = s.as_bytes();
+    let ones = s.iter().take_while(|c| **c == b'1').count();
+    let mut bytes: Vec<u8> = Vec::with_capacity((s.len() - ones) * 733 / 1000 + 1);
+    for c in &s[ones..] {
+        let mut carry = DECODE[*c as usize] as u32;
+        if carry == INVALID as u32 {
+            return None;
+        }
+        for b in bytes.iter_mut() {
+            carry += (*b as u32) * 58;
+            *b = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+    bytes.resize(bytes.len() + ones, 0);
+    bytes.reverse();
+    Some(bytes)
+}
+
+/// Encode a byte slice as Base58Check, which appends the first four bytes of its double SHA-256.
+///
+/// Any version byte is considered part of the payload and should be prepended by the caller.
+pub fn to_string_check(b: &[u8]) -> String {
+    let mut v = Vec::with_capacity(b.len() + CHECKSUM_SIZE);
+    v.extend_from_slice(b);
+    v.extend_from_slice(&checksum(b));
+    to_string(&v)
+}
+
+/// Decode Base58Check, returning the payload without its checksum if the checksum is valid.
+pub fn from_string_check(s: &str) -> Option<Vec<u8>> {
+    let mut v = from_string(s)?;
+    let payload_len = v.len().checked_sub(CHECKSUM_SIZE)?;
+    if checksum(&v[..payload_len]) != v[payload_len..] {
+        return None;
+    }
+    v.truncate(payload_len);
+    Some(v)
+}
+
+fn checksum(b: &[u8]) -> [u8; CHECKSUM_SIZE] {
+    let h = Sha256::digest(Sha256::digest(b));
+    [h[0], h[1], h[2], h[3]]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+
+    #[test]
+    fn vectors() {
+        for (plain, encoded) in [
+            ("", ""),
+            ("61", "2g"),
+            ("626262", "a3gV"),
+            ("636363", "aPEr"),
+            (
+                "73696d706c792061206c6f6e6720737472696e67",
+                "2cFupjhnEsSn59qHXstmK2ffpLv2",
+            ),
+            (
+                "00eb15231dfceb60925886b67d065299925915aeb172c06647",
+                "1NS17iag9jJgTHD1VXjvLCEnZuQ3rJDE9L",
+            ),
+            ("516b6fcd0f", "ABnLTmg"),
+            ("bf4f89001e670274dd", "3SEo3LWLoPntC"),
+            ("572e4794", "3EFU7m"),
+            ("ecac89cad93923c02321", "EJDM8drfXA6uyA"),
+            ("10c8511e", "Rt5zm"),
+            ("00000000000000000000", "1111111111"),
+        ] {
+            let plain = hex::from_string(plain);
+            assert_eq!(to_string(&plain), encoded);
+            assert_eq!(from_string(encoded).unwrap(), plain);
+        }
+        assert!(from_string("0OIl").is_none());
+        assert!(from_string("abc+").is_none());
+    }
+
+    #[test]
+    fn check() {
+        let address = "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa";
+        let payload = from_string_check(address).unwrap();
+        assert_eq!(hex::to_string(&payload), "0062e907b15cbf27d5425399ebf6f0fb50ebb88f18");
+        assert_eq!(to_string_check(&payload), address);
+        assert!(from_string_check("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNb").is_none());
+        assert!(from_string_check("2g").is_none());
+        assert_eq!(from_string_check(&to_string_check(&[])).unwrap(), Vec::<u8>::new());
+    }
+}
@@ -6,8 +6,58 @@
  * https://www.zerotier.com/
  */
 
-use base64::engine::general_purpose;
+use std::io::{Read, Write};
+
+use base64::engine::general_purpose::{self, GeneralPurpose};
+use base64::read::DecoderReader;
+use base64::write::EncoderWriter;
 use base64::Engine as _;
+use zeroize::Zeroize;
+
+static STANDARD: GeneralPurpose = general_purpose::STANDARD;
+static STANDARD_NO_PAD: GeneralPurpose = general_purpose::STANDARD_NO_PAD;
+static URL_SAFE: GeneralPurpose = general_purpose::URL_SAFE;
+static URL_SAFE_NO_PAD: GeneralPurpose = general_purpose::URL_SAFE_NO_PAD;
+
+/// Base64 alphabet and padding combinations, defaulting to the URL-safe unpadded one used by to_string().
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Alphabet {
+    /// RFC 4648 standard alphabet ('+' and '/') with '=' padding.
+    Standard,
+    /// RFC 4648 standard alphabet ('+' and '/') without padding.
+    StandardNoPad,
+    /// RFC 4648 URL-safe alphabet ('-' and '_') with '=' padding.
+    UrlSafe,
+    /// RFC 4648 URL-safe alphabet ('-' and '_') without padding.
+    #[default]
+    UrlSafeNoPad,
+}
+
+impl Alphabet {
+    #[inline(always)]
+    fn engine(self) -> &'static GeneralPurpose {
+        match self {
+            Self::Standard => &STANDARD,
+            Self::StandardNoPad => &STANDARD_NO_PAD,
+            Self::UrlSafe => &URL_SAFE,
+            Self::UrlSafeNoPad => &URL_SAFE_NO_PAD,
+        }
+    }
+
+    #[inline(always)]
+    fn padded(self) -> bool {
+        matches!(self, Self::Standard | Self::UrlSafe)
+    }
+
+    /// The characters used for values 62 and 63.
+    #[inline(always)]
+    fn extra_chars(self) -> (u8, u8) {
+        match self {
+            Self::Standard | Self::StandardNoPad => (b'+', b'/'),
+            Self::UrlSafe | Self::UrlSafeNoPad => (b'-', b'_'),
+        }
+    }
+}
 
 /// Encode a byte slice as Base64 using the URL-safe alphabet without padding
 #[inline(always)]
@@ -20,3 +70,250 @@ pub fn to_string(b: &[u8]) -> String {
 pub fn from_string(s: &[u8]) -> Option<Vec<u8>> {
     general_purpose::URL_SAFE_NO_PAD.decode(s).ok()
 }
+
+/// Encode a byte slice as Base64 using the given alphabet.
+#[inline(always)]
+pub fn to_string_with(b: &[u8], alphabet: Alphabet) -> String {
+    alphabet.engine().encode(b)
+}
+
+/// Decode a byte slice using the given alphabet.
+///
+/// Padding must be present and canonical for padded alphabets and absent otherwise.
+#[inline(always)]
+pub fn from_string_with(s: &[u8], alphabet: Alphabet) -> Option<Vec<u8>> {
+    alphabet.engine().decode(s).ok()
+}
+
+/// All ones if lo <= c <= hi, otherwise zero, without branches or table lookups.
+#[inline(always)]
+fn ct_in_range(c: i16, lo: u8, hi: u8) -> i16 {
+    (((lo as i16) - 1 - c) & (c - (hi as i16) - 1)) >> 15
+}
+
+/// Decode a character to its 6-bit value, or -1 if it is not in the alphabet, in constant time.
+#[inline(always)]
+fn ct_decode_char(c: u8, extra: (u8, u8)) -> i16 {
+    let c = c as i16;
+    let mut v = -1;
+    v += ct_in_range(c, b'A', b'Z') & (c - (b'A' as i16) + 1);
+    v += ct_in_range(c, b'a', b'z') & (c - (b'a' as i16) + 27);
+    v += ct_in_range(c, b'0', b'9') & (c - (b'0' as i16) + 53);
+    v += ct_in_range(c, extra.0, extra.0) & 63;
+    v += ct_in_range(c, extra.1, extra.1) & 64;
+    v
+}
+
+/// Decode a byte slice in time that depends only on its length, for use with secret key material.
+///
+/// This accepts exactly the same input as from_string_with() but avoids data-dependent branches
+/// and table lookups, so neither the decoded bytes nor the location of an invalid character leak
+/// through timing. The amount of padding is treated as public since it follows from the length.
+pub fn from_string_ct(s: &[u8], alphabet: Alphabet) -> Option<Vec<u8>> {
+    let mut data_len = s.len();
+    if alphabet.padded() {
+        if !s.len().is_multiple_of(4) {
+            return None;
+        }
+        // At most two trailing '=' can be padding. Any others are rejected below as invalid characters.
+        data_len -= s.iter().rev().take(2).take_while(|c| **c == b'=').count();
+    }
+    if (data_len % 4) == 1 {
+        return None;
+    }
+
+    let extra = alphabet.extra_chars();
+    let mut out = Vec::with_capacity(data_len * 3 / 4);
+    let mut invalid = 0_i16;
+    let mut acc = 0_u32;
+    for chunk in s[..data_len].chunks(4) {
+        for c in chunk {
+            let v = ct_decode_char(*c, extra);
+            invalid |= v;
+            acc = (acc << 6) | ((v & 0x3f) as u32);
+        }
+        match chunk.len() {
+            4 => out.extend_from_slice(&acc.to_be_bytes()[1..]),
+            3 => {
+                invalid |= ((acc & 0x3) as i16) << 6;
+                out.extend_from_slice(&(acc >> 2).to_be_bytes()[2..]);
+            }
+            _ => {
+                invalid |= ((acc & 0xf) as i16) << 6;
+                out.push((acc >> 4) as u8);
+            }
+        }
+    }
+    acc.zeroize();
+
+    // A valid character decodes to 0..63 and an invalid one to -1, and nonzero trailing bits were
+    // shifted up above the lowest six, so any higher set bit means the input was invalid.
+    if (invalid & !0x3f) != 0 {
+        out.as_mut_slice().zeroize();
+        None
+    } else {
+        Some(out)
+    }
+}
+
+/// Streaming Base64 encoder that writes encoded text to an underlying writer.
+///
+/// Call finish() to write the final partial block and get the writer back. If the encoder is
+/// dropped without finishing, the final block is written and any error is ignored.
+pub struct Encoder<W: Write>(EncoderWriter<'static, GeneralPurpose, W>);
+
+impl<W: Write> Encoder<W> {
+    #[inline(always)]
+    pub fn new(w: W, alphabet: Alphabet) -> Self {
+        Self(EncoderWriter::new(w, alphabet.engine()))
+    }
+
+    /// Write any buffered input and padding and return the underlying writer.
+    #[inline(always)]
+    pub fn finish(mut self) -> std::io::Result<W> {
+        self.0.finish()
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    #[inline(always)]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    #[inline(always)]
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Streaming Base64 decoder that reads encoded text from an underlying reader.
+///
+/// Invalid input results in an InvalidData error. Whitespace is not skipped.
+pub struct Decoder<R: Read>(DecoderReader<'static, GeneralPurpose, R>);
+
+impl<R: Read> Decoder<R> {
+    #[inline(always)]
+    pub fn new(r: R, alphabet: Alphabet) -> Self {
+        Self(DecoderReader::new(r, alphabet.engine()))
+    }
+
+    #[inline(always)]
+    pub fn into_inner(self) -> R {
+        self.0.into_inner()
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    #[inline(always)]
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use super::*;
+
+    const ALPHABETS: [Alphabet; 4] = [
+        Alphabet::Standard,
+        Alphabet::StandardNoPad,
+        Alphabet::UrlSafe,
+        Alphabet::UrlSafeNoPad,
+    ];
+
+    #[test]
+    fn alphabets() {
+        let b = [0xfb, 0xff, 0x01];
+        assert_eq!(to_string_with(&b, Alphabet::Standard), "+/8B");
+        assert_eq!(to_string_with(&b, Alphabet::UrlSafe), "-_8B");
+        assert_eq!(to_string_with(&b[..2], Alphabet::Standard), "+/8=");
+        assert_eq!(to_string_with(&b[..2], Alphabet::StandardNoPad), "+/8");
+        assert_eq!(to_string_with(&b[..1], Alphabet::UrlSafe), "-w==");
+        assert_eq!(to_string(&b[..1]), to_string_with(&b[..1], Alphabet::default()));
+        assert!(from_string_with(b"+/8=", Alphabet::Standard).is_some());
+        assert!(from_string_with(b"+/8", Alphabet::Standard).is_none());
+        assert!(from_string_with(b"+/8=", Alphabet::StandardNoPad).is_none());
+        assert!(from_string_with(b"-_8B", Alphabet::Standard).is_none());
+    }
+
+    #[test]
+    fn constant_time_decode_matches() {
+        let mut data = [0_u8; 64];
+        for len in 0..data.len() {
+            data[..len].fill_with(rand::random);
+            for alphabet in ALPHABETS {
+                let s = to_string_with(&data[..len], alphabet);
+                assert_eq!(from_string_ct(s.as_bytes(), alphabet).unwrap(), &data[..len]);
+            }
+        }
+        for alphabet in ALPHABETS {
+            for s in [
+                "",
+                "A",
+                "AA",
+                "AB",
+                "AAA",
+                "AAB",
+                "AAAA",
+                "A===",
+                "AA==",
+                "AB==",
+                "AAA=",
+                "AAB=",
+                "AA=",
+                "AA===",
+                "====",
+                "AA=A",
+                "+/+/",
+                "-_-_",
+                "AA AA",
+                "AAAA\n",
+                "\u{e9}AAA",
+                "AAAA====",
+            ] {
+                assert_eq!(
+                    from_string_ct(s.as_bytes(), alphabet),
+                    from_string_with(s.as_bytes(), alphabet),
+                    "{:?} {:?}",
+                    s,
+                    alphabet
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn streaming() {
+        let data: Vec<u8> = (0..10000_u32).map(|i| (i * 7 + i / 251) as u8).collect();
+        for alphabet in ALPHABETS {
+            for chunk_size in [1, 2, 3, 7, 64, 4096] {
+                let mut e = Encoder::new(Vec::new(), alphabet);
+                for chunk in data.chunks(chunk_size) {
+                    e.write_all(chunk).unwrap();
+                }
+                let encoded = e.finish().unwrap();
+                assert_eq!(encoded, to_string_with(&data, alphabet).as_bytes());
+
+                let mut d = Decoder::new(encoded.as_slice(), alphabet);
+                let mut decoded = Vec::new();
+                let mut buf = vec![0_u8; chunk_size];
+                loop {
+                    let n = d.read(&mut buf).unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    decoded.extend_from_slice(&buf[..n]);
+                }
+                assert_eq!(decoded, data);
+            }
+        }
+        let mut d = Decoder::new(b"AAAA!AAA".as_slice(), Alphabet::Standard);
+        assert_eq!(
+            d.read_to_end(&mut Vec::new()).unwrap_err().kind(),
+            std::io::ErrorKind::InvalidData
+        );
+    }
+}
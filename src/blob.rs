@@ -7,14 +7,23 @@
  */
 
 use std::array::TryFromSliceError;
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
 use std::hash::Hash;
+use std::io::{Read, Write};
 use std::ops::{Deref, DerefMut};
+use std::str::FromStr;
 
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use zeroize::Zeroize;
 
-use crate::{base64, hex};
+use crate::error::InvalidParameterError;
+use crate::tofrombytes::ToFromBytes;
+use crate::{base32, base58, base64, hex};
+
+mod ops;
+mod secret;
+
+pub use secret::SecretBlob;
 
 /// Fixed size Serde serializable byte array.
 /// This makes it easier to deal with blobs larger than 32 bytes (due to serde array limitations)
@@ -99,10 +108,53 @@ impl<const L: usize> AsMut<[u8; L]> for Blob<L> {
     }
 }
 
-impl<const L: usize> ToString for Blob<L> {
+impl<const L: usize> Display for Blob<L> {
+    #[inline]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(hex::to_string(&self.0).as_str())
+    }
+}
+
+impl<const L: usize> FromStr for Blob<L> {
+    type Err = InvalidParameterError;
+
+    /// Parse hex or base64, detected by length and alphabet.
+    ///
+    /// A string of exactly L * 2 hex digits is always read as hex. Anything else is read as base64,
+    /// using the standard alphabet if it contains '+' or '/' and the URL-safe one otherwise, with or
+    /// without padding.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let b = if s.len() == L * 2 && s.bytes().all(|c| c.is_ascii_hexdigit()) {
+            hex::decode(s).ok()
+        } else {
+            let alphabet = match (s.contains(['+', '/']), s.ends_with('=')) {
+                (true, true) => base64::Alphabet::Standard,
+                (true, false) => base64::Alphabet::StandardNoPad,
+                (false, true) => base64::Alphabet::UrlSafe,
+                (false, false) => base64::Alphabet::UrlSafeNoPad,
+            };
+            base64::from_string_with(s.as_bytes(), alphabet)
+        };
+        let b = b.ok_or(InvalidParameterError("invalid hex or base64"))?;
+        b.as_slice()
+            .try_into()
+            .map(Self)
+            .map_err(|_| InvalidParameterError("wrong length"))
+    }
+}
+
+impl<const L: usize> ToFromBytes for Blob<L> {
+    #[inline]
+    fn read_bytes<R: Read>(r: &mut R) -> std::io::Result<Self> {
+        let mut b = Self::default();
+        r.read_exact(&mut b.0)?;
+        Ok(b)
+    }
+
     #[inline(always)]
-    fn to_string(&self) -> String {
-        hex::to_string(&self.0)
+    fn write_bytes<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        w.write_all(&self.0)
     }
 }
 
@@ -119,15 +171,12 @@ impl<const L: usize> Serialize for Blob<L> {
     where
         S: Serializer,
     {
-        if serializer.is_human_readable() {
-            base64::to_string(&self.0).serialize(serializer)
-        } else {
-            serializer.serialize_bytes(&self.0)
-        }
+        serialize_encoded(self, serializer, base64::to_string)
     }
 }
 
-struct BlobVisitor<const L: usize>;
+/// Visitor accepting raw bytes or a string in the text encoding given by its decoder.
+struct BlobVisitor<const L: usize>(fn(&str) -> Option<Vec<u8>>, &'static str);
 
 impl<'de, const L: usize> serde::de::Visitor<'de> for BlobVisitor<L> {
     type Value = Blob<L>;
@@ -142,11 +191,15 @@ impl<'de, const L: usize> serde::de::Visitor<'de> for BlobVisitor<L> {
     where
         E: serde::de::Error,
     {
-        let b = base64::from_string(v.trim().as_bytes()).ok_or(serde::de::Error::custom("invalid base64"))?;
-        b.as_slice()
+        let mut b = self.0(v.trim()).ok_or(serde::de::Error::custom(format!("invalid {}", self.1)))?;
+        let r = b
+            .as_slice()
             .try_into()
             .map(|b| Blob::<L>(b))
-            .map_err(|_| serde::de::Error::invalid_length(b.len(), &self))
+            .map_err(|_| serde::de::Error::invalid_length(b.len(), &self));
+        // The decoded bytes may be key material.
+        b.as_mut_slice().zeroize();
+        r
     }
 
     fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
@@ -159,16 +212,293 @@ impl<'de, const L: usize> serde::de::Visitor<'de> for BlobVisitor<L> {
     }
 }
 
+#[inline]
+fn serialize_encoded<S: Serializer, const L: usize>(
+    blob: &Blob<L>,
+    serializer: S,
+    encode: fn(&[u8]) -> String,
+) -> Result<S::Ok, S::Error> {
+    if serializer.is_human_readable() {
+        serializer.serialize_str(encode(&blob.0).as_str())
+    } else {
+        serializer.serialize_bytes(&blob.0)
+    }
+}
+
+#[inline]
+fn deserialize_encoded<'de, D: Deserializer<'de>, const L: usize>(
+    deserializer: D,
+    visitor: BlobVisitor<L>,
+) -> Result<Blob<L>, D::Error> {
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_str(visitor)
+    } else {
+        deserializer.deserialize_bytes(visitor)
+    }
+}
+
 impl<'de, const L: usize> Deserialize<'de> for Blob<L> {
     #[inline]
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        if deserializer.is_human_readable() {
-            deserializer.deserialize_str(BlobVisitor::<L>)
-        } else {
-            deserializer.deserialize_bytes(BlobVisitor::<L>)
+        deserialize_encoded(
+            deserializer,
+            BlobVisitor(|s| base64::from_string(s.as_bytes()), "base64"),
+        )
+    }
+}
+
+/// Serialize a Blob as lowercase hex in human readable formats.
+///
+/// Use with #[serde(with = "blob::serde_hex")]. Uppercase input is also accepted.
+pub mod serde_hex {
+    use super::*;
+
+    #[inline]
+    pub fn serialize<S: Serializer, const L: usize>(blob: &Blob<L>, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_encoded(blob, serializer, hex::to_string)
+    }
+
+    #[inline]
+    pub fn deserialize<'de, D: Deserializer<'de>, const L: usize>(deserializer: D) -> Result<Blob<L>, D::Error> {
+        deserialize_encoded(deserializer, BlobVisitor(|s| hex::decode(s).ok(), "hex"))
+    }
+}
+
+/// Serialize a Blob as URL-safe unpadded base64 in human readable formats, the same as its default.
+///
+/// Use with #[serde(with = "blob::serde_base64")].
+pub mod serde_base64 {
+    use super::*;
+
+    #[inline]
+    pub fn serialize<S: Serializer, const L: usize>(blob: &Blob<L>, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_encoded(blob, serializer, base64::to_string)
+    }
+
+    #[inline]
+    pub fn deserialize<'de, D: Deserializer<'de>, const L: usize>(deserializer: D) -> Result<Blob<L>, D::Error> {
+        deserialize_encoded(
+            deserializer,
+            BlobVisitor(|s| base64::from_string(s.as_bytes()), "base64"),
+        )
+    }
+}
+
+/// Serialize a Blob as unpadded RFC 4648 base32 in human readable formats.
+///
+/// Use with #[serde(with = "blob::serde_base32")]. Padded input is also accepted.
+pub mod serde_base32 {
+    use super::*;
+
+    #[inline]
+    pub fn serialize<S: Serializer, const L: usize>(blob: &Blob<L>, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_encoded(blob, serializer, base32::to_string_unpadded)
+    }
+
+    #[inline]
+    pub fn deserialize<'de, D: Deserializer<'de>, const L: usize>(deserializer: D) -> Result<Blob<L>, D::Error> {
+        deserialize_encoded(deserializer, BlobVisitor(base32::from_string, "base32"))
+    }
+}
+
+/// Serialize a Blob as Crockford base32 in human readable formats.
+///
+/// Use with #[serde(with = "blob::serde_base32_crockford")].
+pub mod serde_base32_crockford {
+    use super::*;
+
+    #[inline]
+    pub fn serialize<S: Serializer, const L: usize>(blob: &Blob<L>, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_encoded(blob, serializer, base32::to_string_crockford)
+    }
+
+    #[inline]
+    pub fn deserialize<'de, D: Deserializer<'de>, const L: usize>(deserializer: D) -> Result<Blob<L>, D::Error> {
+        deserialize_encoded(deserializer, BlobVisitor(base32::from_string_crockford, "base32"))
+    }
+}
+
+/// Serialize a Blob as Base58 in human readable formats.
+///
+/// Use with #[serde(with = "blob::serde_base58")].
+pub mod serde_base58 {
+    use super::*;
+
+    #[inline]
+    pub fn serialize<S: Serializer, const L: usize>(blob: &Blob<L>, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_encoded(blob, serializer, base58::to_string)
+    }
+
+    #[inline]
+    pub fn deserialize<'de, D: Deserializer<'de>, const L: usize>(deserializer: D) -> Result<Blob<L>, D::Error> {
+        deserialize_encoded(deserializer, BlobVisitor(base58::from_string, "base58"))
+    }
+}
+
+/// Serialize a Blob as Base58Check in human readable formats.
+///
+/// Use with #[serde(with = "blob::serde_base58check")].
+pub mod serde_base58check {
+    use super::*;
+
+    #[inline]
+    pub fn serialize<S: Serializer, const L: usize>(blob: &Blob<L>, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_encoded(blob, serializer, base58::to_string_check)
+    }
+
+    #[inline]
+    pub fn deserialize<'de, D: Deserializer<'de>, const L: usize>(deserializer: D) -> Result<Blob<L>, D::Error> {
+        deserialize_encoded(deserializer, BlobVisitor(base58::from_string_check, "base58check"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use serde::{Deserialize, Serialize};
+
+    use super::{Blob, SecretBlob};
+    use crate::tofrombytes::ToFromBytes;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Encodings {
+        default: Blob<4>,
+        #[serde(with = "crate::blob::serde_hex")]
+        hex: Blob<4>,
+        #[serde(with = "crate::blob::serde_base64")]
+        base64: Blob<4>,
+        #[serde(with = "crate::blob::serde_base32")]
+        base32: Blob<4>,
+        #[serde(with = "crate::blob::serde_base32_crockford")]
+        crockford: Blob<4>,
+        #[serde(with = "crate::blob::serde_base58")]
+        base58: Blob<4>,
+        #[serde(with = "crate::blob::serde_base58check")]
+        base58check: Blob<4>,
+    }
+
+    #[test]
+    fn serde_encodings() {
+        let b = Blob::from(*b"foob");
+        let e = Encodings {
+            default: b.clone(),
+            hex: b.clone(),
+            base64: b.clone(),
+            base32: b.clone(),
+            crockford: b.clone(),
+            base58: b.clone(),
+            base58check: b,
+        };
+        let json = serde_json::to_string(&e).unwrap();
+        assert_eq!(
+            json,
+            r#"{"default":"Zm9vYg","hex":"666f6f62","base64":"Zm9vYg","base32":"MZXW6YQ","crockford":"CSQPYRG","base58":"3csAg9","base58check":"J8kYDxrcwNC"}"#
+        );
+        assert_eq!(serde_json::from_str::<Encodings>(&json).unwrap(), e);
+        assert!(serde_json::from_str::<Encodings>(&json.replace("MZXW6YQ", "MZXW6YTB")).is_err());
+        assert!(serde_json::from_str::<Encodings>(&json.replace("666f6f62", "666f6f6")).is_err());
+        assert!(serde_json::from_str::<Encodings>(&json.replace("J8kYDxrcwNC", "J8kYDxrcwND")).is_err());
+    }
+
+    #[test]
+    fn secret_blob() {
+        let a = SecretBlob::from([1_u8, 2, 3, 4]);
+        let b = SecretBlob::from(Blob::from([1_u8, 2, 3, 4]));
+        let c = SecretBlob::<4>::try_from([1_u8, 2, 3, 5].as_slice()).unwrap();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_eq!(a.expose_secret(), &[1, 2, 3, 4]);
+        assert_eq!(format!("{:?}", a), "SecretBlob<4>(redacted)");
+        assert_eq!(format!("{}", a), "(redacted)");
+
+        let json = serde_json::to_string(&a).unwrap();
+        assert_eq!(json, serde_json::to_string(&Blob::from([1_u8, 2, 3, 4])).unwrap());
+        assert_eq!(serde_json::from_str::<SecretBlob<4>>(&json).unwrap(), a);
+        assert!(serde_json::from_str::<SecretBlob<4>>("\"AQIDBA!\"").is_err());
+        assert!(serde_json::from_str::<SecretBlob<4>>("\"AQID\"").is_err());
+    }
+
+    #[test]
+    fn bitwise_and_arithmetic() {
+        let a = Blob::from([0x0f_u8, 0xf0, 0x00]);
+        let b = Blob::from([0x01_u8, 0x10, 0xff]);
+        assert_eq!((&a ^ &b).as_bytes(), &[0x0e, 0xe0, 0xff]);
+        assert_eq!((&a & &b).as_bytes(), &[0x01, 0x10, 0x00]);
+        assert_eq!((a.clone() | b.clone()).as_bytes(), &[0x0f, 0xf0, 0xff]);
+        assert_eq!((!&a).as_bytes(), &[0xf0, 0x0f, 0xff]);
+        let mut c = a.clone();
+        c ^= &a;
+        assert_eq!(c, Blob::default());
+
+        assert_eq!(Blob::<3>::default().leading_zeros(), 24);
+        assert_eq!(a.leading_zeros(), 4);
+        assert_eq!(Blob::from([0_u8, 0, 1]).leading_zeros(), 23);
+        assert_eq!(a.common_prefix_bits(&a), 24);
+        assert_eq!(a.common_prefix_bits(&b), 4);
+        assert_eq!(a.common_prefix_bits(&Blob::from([0x0f, 0xf0, 0x80])), 16);
+
+        let mut x = Blob::from([0x00_u8, 0xff, 0xff]);
+        assert!(!x.increment());
+        assert_eq!(x.as_bytes(), &[0x01, 0x00, 0x00]);
+        let mut x = Blob::from([0xff_u8; 3]);
+        assert!(x.increment());
+        assert_eq!(x, Blob::default());
+        assert_eq!((&a + &b).as_bytes(), &[0x11, 0x00, 0xff]);
+        let (sum, overflow) = Blob::from([0xff_u8, 0xff, 0x01]).overflowing_add(&Blob::from([0, 0, 0xff]));
+        assert_eq!((sum.as_bytes(), overflow), (&[0, 0, 0], true));
+        let mut y = b.clone();
+        y += &Blob::from([0, 0, 1]);
+        assert_eq!(y.as_bytes(), &[0x01, 0x11, 0x00]);
+
+        let target = Blob::from([0x80_u8, 0x00, 0x00]);
+        let mut candidates = [
+            Blob::from([0x00_u8, 0x00, 0x00]),
+            Blob::from([0x80_u8, 0x00, 0x01]),
+            Blob::from([0xc0_u8, 0x00, 0x00]),
+            Blob::from([0x81_u8, 0x00, 0x00]),
+        ];
+        candidates.sort_by(|a, b| target.cmp_distance(a, b));
+        assert_eq!(candidates[0].as_bytes(), &[0x80, 0x00, 0x01]);
+        assert_eq!(candidates[1].as_bytes(), &[0x81, 0x00, 0x00]);
+        assert_eq!(candidates[2].as_bytes(), &[0xc0, 0x00, 0x00]);
+        assert_eq!(candidates[3].as_bytes(), &[0x00, 0x00, 0x00]);
+        assert!(target.is_closer(&candidates[0], &candidates[1]));
+        assert!(!target.is_closer(&candidates[1], &candidates[1]));
+        assert_eq!(target.xor_distance(&candidates[1]).as_bytes(), &[0x01, 0, 0]);
+    }
+
+    #[test]
+    fn string_and_bytes() {
+        let b = Blob::from([0xfb_u8, 0xff, 0x01, 0x00, 0x7f]);
+        assert_eq!(b.to_string(), "fbff01007f");
+        assert_eq!(format!("{:?}", b), "fbff01007f");
+        for s in [
+            "fbff01007f",
+            "FBFF01007F",
+            " fbff01007f\n",
+            "-_8BAH8",
+            "+/8BAH8",
+            "+/8BAH8=",
+            "-_8BAH8=",
+        ] {
+            assert_eq!(Blob::<5>::from_str(s).unwrap(), b, "{}", s);
         }
+        assert!(Blob::<5>::from_str("fbff01007").is_err());
+        assert!(Blob::<5>::from_str("fbff01007f00").is_err());
+        assert!(Blob::<5>::from_str("-_8BAH8A").is_err());
+        assert!(Blob::<5>::from_str("+_8BAH8").is_err());
+
+        // Two hex digits are also valid base64 for one byte, but hex wins.
+        assert_eq!(Blob::<1>::from_str("AA").unwrap().as_bytes(), &[0xaa]);
+        assert_eq!(Blob::<1>::from_str("AA==").unwrap().as_bytes(), &[0x00]);
+
+        let bytes = b.to_bytes();
+        assert_eq!(bytes, b.as_bytes());
+        assert_eq!(Blob::<5>::from_bytes(&bytes).unwrap(), b);
+        assert!(Blob::<5>::from_bytes(&bytes[..4]).is_err());
     }
 }
@@ -0,0 +1,190 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ *
+ * (c) ZeroTier, Inc.
+ * https://www.zerotier.com/
+ */
+
+use std::cmp::Ordering;
+use std::ops::{Add, AddAssign, BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not};
+
+use super::Blob;
+
+/// Blobs are treated as big-endian unsigned integers L bytes wide for arithmetic and bit counting.
+impl<const L: usize> Blob<L> {
+    /// Number of leading zero bits, which is L * 8 if all bytes are zero.
+    #[inline]
+    pub fn leading_zeros(&self) -> u32 {
+        let mut n = 0;
+        for b in self.0.iter() {
+            n += b.leading_zeros();
+            if *b != 0 {
+                break;
+            }
+        }
+        n
+    }
+
+    /// Number of leading bits that are the same in both blobs.
+    #[inline]
+    pub fn common_prefix_bits(&self, other: &Self) -> u32 {
+        let mut n = 0;
+        for (a, b) in self.0.iter().zip(other.0.iter()) {
+            let x = a ^ b;
+            n += x.leading_zeros();
+            if x != 0 {
+                break;
+            }
+        }
+        n
+    }
+
+    /// Add one, returning true if the value wrapped around to zero.
+    #[inline]
+    pub fn increment(&mut self) -> bool {
+        for b in self.0.iter_mut().rev() {
+            let (x, carry) = b.overflowing_add(1);
+            *b = x;
+            if !carry {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Add another blob, returning the wrapped sum and whether it overflowed.
+    #[inline]
+    pub fn overflowing_add(&self, rhs: &Self) -> (Self, bool) {
+        let mut sum = self.clone();
+        let mut carry = false;
+        for (a, b) in sum.0.iter_mut().zip(rhs.0.iter()).rev() {
+            let (x, c0) = a.overflowing_add(*b);
+            let (x, c1) = x.overflowing_add(carry as u8);
+            *a = x;
+            carry = c0 || c1;
+        }
+        (sum, carry)
+    }
+
+    /// XOR distance metric as used by Kademlia.
+    #[inline(always)]
+    pub fn xor_distance(&self, other: &Self) -> Self {
+        self ^ other
+    }
+
+    /// Compare the XOR distances from this blob to a and b without computing either one.
+    ///
+    /// This can be used to sort lookup candidates by closeness, e.g.
+    /// candidates.sort_by(|a, b| target.cmp_distance(a, b)).
+    #[inline]
+    pub fn cmp_distance(&self, a: &Self, b: &Self) -> Ordering {
+        for ((t, a), b) in self.0.iter().zip(a.0.iter()).zip(b.0.iter()) {
+            match (t ^ a).cmp(&(t ^ b)) {
+                Ordering::Equal => {}
+                o => return o,
+            }
+        }
+        Ordering::Equal
+    }
+
+    /// Returns true if a is strictly closer to this blob than b by XOR distance.
+    #[inline(always)]
+    pub fn is_closer(&self, a: &Self, b: &Self) -> bool {
+        self.cmp_distance(a, b) == Ordering::Less
+    }
+}
+
+macro_rules! bitwise_op {
+    ($trait:ident, $method:ident, $assign_trait:ident, $assign_method:ident, $op:tt) => {
+        impl<const L: usize> $assign_trait<&Blob<L>> for Blob<L> {
+            #[inline]
+            fn $assign_method(&mut self, rhs: &Blob<L>) {
+                for (a, b) in self.0.iter_mut().zip(rhs.0.iter()) {
+                    *a $op *b;
+                }
+            }
+        }
+
+        impl<const L: usize> $assign_trait for Blob<L> {
+            #[inline(always)]
+            fn $assign_method(&mut self, rhs: Blob<L>) {
+                self.$assign_method(&rhs);
+            }
+        }
+
+        impl<const L: usize> $trait for &Blob<L> {
+            type Output = Blob<L>;
+
+            #[inline(always)]
+            fn $method(self, rhs: &Blob<L>) -> Blob<L> {
+                let mut r = self.clone();
+                r.$assign_method(rhs);
+                r
+            }
+        }
+
+        impl<const L: usize> $trait for Blob<L> {
+            type Output = Blob<L>;
+
+            #[inline(always)]
+            fn $method(mut self, rhs: Blob<L>) -> Blob<L> {
+                self.$assign_method(&rhs);
+                self
+            }
+        }
+    };
+}
+
+bitwise_op!(BitXor, bitxor, BitXorAssign, bitxor_assign, ^=);
+bitwise_op!(BitAnd, bitand, BitAndAssign, bitand_assign, &=);
+bitwise_op!(BitOr, bitor, BitOrAssign, bitor_assign, |=);
+
+impl<const L: usize> Not for Blob<L> {
+    type Output = Blob<L>;
+
+    #[inline]
+    fn not(mut self) -> Blob<L> {
+        for b in self.0.iter_mut() {
+            *b = !*b;
+        }
+        self
+    }
+}
+
+impl<const L: usize> Not for &Blob<L> {
+    type Output = Blob<L>;
+
+    #[inline(always)]
+    fn not(self) -> Blob<L> {
+        !self.clone()
+    }
+}
+
+/// Wrapping big-endian addition.
+impl<const L: usize> Add for &Blob<L> {
+    type Output = Blob<L>;
+
+    #[inline(always)]
+    fn add(self, rhs: &Blob<L>) -> Blob<L> {
+        self.overflowing_add(rhs).0
+    }
+}
+
+/// Wrapping big-endian addition.
+impl<const L: usize> Add for Blob<L> {
+    type Output = Blob<L>;
+
+    #[inline(always)]
+    fn add(self, rhs: Blob<L>) -> Blob<L> {
+        self.overflowing_add(&rhs).0
+    }
+}
+
+/// Wrapping big-endian addition.
+impl<const L: usize> AddAssign<&Blob<L>> for Blob<L> {
+    #[inline(always)]
+    fn add_assign(&mut self, rhs: &Blob<L>) {
+        *self = self.overflowing_add(rhs).0;
+    }
+}
@@ -0,0 +1,179 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ *
+ * (c) ZeroTier, Inc.
+ * https://www.zerotier.com/
+ */
+
+use std::array::TryFromSliceError;
+use std::fmt::{Debug, Display};
+
+use serde::de::Visitor;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use zeroize::Zeroize;
+
+use super::Blob;
+use crate::base64;
+
+/// Fixed size byte array for secret material such as private keys.
+///
+/// Unlike Blob this compares in constant time, does not implement Ord or Hash, never prints its
+/// contents, and is zeroed when dropped. The bytes are only reachable through expose_secret().
+/// Serialization is the same as Blob's: base64 for human readable formats and bytes otherwise.
+#[repr(transparent)]
+#[derive(Clone)]
+pub struct SecretBlob<const L: usize>([u8; L]);
+
+impl<const L: usize> SecretBlob<L> {
+    #[inline(always)]
+    pub fn expose_secret(&self) -> &[u8; L] {
+        &self.0
+    }
+
+    #[inline(always)]
+    pub fn expose_secret_mut(&mut self) -> &mut [u8; L] {
+        &mut self.0
+    }
+}
+
+impl<const L: usize> Drop for SecretBlob<L> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl<const L: usize> Zeroize for SecretBlob<L> {
+    #[inline(always)]
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl<const L: usize> PartialEq for SecretBlob<L> {
+    /// Compare in time that does not depend on where the first differing byte is.
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        let mut d = 0_u8;
+        for (a, b) in self.0.iter().zip(other.0.iter()) {
+            d |= a ^ b;
+        }
+        std::hint::black_box(d) == 0
+    }
+}
+
+impl<const L: usize> Eq for SecretBlob<L> {}
+
+impl<const L: usize> From<[u8; L]> for SecretBlob<L> {
+    #[inline(always)]
+    fn from(a: [u8; L]) -> Self {
+        Self(a)
+    }
+}
+
+impl<const L: usize> From<Blob<L>> for SecretBlob<L> {
+    #[inline(always)]
+    fn from(mut b: Blob<L>) -> Self {
+        let s = Self(b.0);
+        b.zeroize();
+        s
+    }
+}
+
+impl<const L: usize> TryFrom<&[u8]> for SecretBlob<L> {
+    type Error = TryFromSliceError;
+
+    #[inline(always)]
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        value.try_into().map(Self)
+    }
+}
+
+impl<const L: usize> Default for SecretBlob<L> {
+    #[inline(always)]
+    fn default() -> Self {
+        Self([0; L])
+    }
+}
+
+impl<const L: usize> Debug for SecretBlob<L> {
+    #[inline]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SecretBlob<{}>(redacted)", L)
+    }
+}
+
+impl<const L: usize> Display for SecretBlob<L> {
+    #[inline]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("(redacted)")
+    }
+}
+
+impl<const L: usize> Serialize for SecretBlob<L> {
+    #[inline]
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if serializer.is_human_readable() {
+            let mut s = base64::to_string(&self.0);
+            let r = serializer.serialize_str(s.as_str());
+            // Zeros are valid UTF-8 and the string is dropped right after.
+            unsafe { s.as_mut_vec() }.as_mut_slice().zeroize();
+            r
+        } else {
+            serializer.serialize_bytes(&self.0)
+        }
+    }
+}
+
+/// Decodes directly into a SecretBlob so no copy of the secret outlives deserialization.
+struct SecretBlobVisitor<const L: usize>;
+
+impl<'de, const L: usize> Visitor<'de> for SecretBlobVisitor<L> {
+    type Value = SecretBlob<L>;
+
+    #[inline]
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str(format!("{} bytes", L).as_str())
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        let mut b = base64::from_string_ct(v.trim().as_bytes(), base64::Alphabet::UrlSafeNoPad)
+            .ok_or(E::custom("invalid base64"))?;
+        let r = self.visit_bytes(b.as_slice());
+        b.as_mut_slice().zeroize();
+        r
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        if v.len() != L {
+            return Err(E::invalid_length(v.len(), &self));
+        }
+        let mut s = SecretBlob::default();
+        s.0.copy_from_slice(v);
+        Ok(s)
+    }
+}
+
+impl<'de, const L: usize> Deserialize<'de> for SecretBlob<L> {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(SecretBlobVisitor)
+        } else {
+            deserializer.deserialize_bytes(SecretBlobVisitor)
+        }
+    }
+}
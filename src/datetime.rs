@@ -0,0 +1,440 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ *
+ * (c) ZeroTier, Inc.
+ * https://www.zerotier.com/
+ */
+
+use std::fmt::Write;
+
+use crate::error::InvalidParameterError;
+
+const MS_PER_DAY: i64 = 86400000;
+
+/// Years beyond this are outside the range of i64 milliseconds (about 292 million years), and
+/// bounding them first keeps days_from_civil() from overflowing.
+const MAX_ABS_YEAR: u64 = 300_000_000;
+
+/// A UTC (or fixed offset) timestamp broken down into calendar fields.
+///
+/// Dates use the proleptic Gregorian calendar, so any i64 millisecond value within a few hundred
+/// million years of the epoch can be represented.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub struct CivilTime {
+    pub year: i64,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub millisecond: u16,
+}
+
+/// Days since 1970-01-01 from a year, month (1-12) and day (1-31).
+fn days_from_civil(y: i64, m: u8, d: u8) -> i64 {
+    let y = if m <= 2 {
+        y - 1
+    } else {
+        y
+    };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (m as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + d as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Year, month (1-12) and day (1-31) from days since 1970-01-01.
+fn civil_from_days(z: i64) -> (i64, u8, u8) {
+    let z = z + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let m = if mp < 10 {
+        mp + 3
+    } else {
+        mp - 9
+    } as u8;
+    (yoe + era * 400 + (m <= 2) as i64, m, d)
+}
+
+fn days_in_month(y: i64, m: u8) -> u8 {
+    match m {
+        2 => {
+            if (y % 4 == 0 && y % 100 != 0) || y % 400 == 0 {
+                29
+            } else {
+                28
+            }
+        }
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl CivilTime {
+    /// Break down milliseconds since the epoch (e.g. from ms_since_epoch()) in UTC.
+    pub fn from_epoch_ms(ms: i64) -> Self {
+        let days = ms.div_euclid(MS_PER_DAY);
+        let ms_of_day = ms.rem_euclid(MS_PER_DAY);
+        let (year, month, day) = civil_from_days(days);
+        Self {
+            year,
+            month,
+            day,
+            hour: (ms_of_day / 3600000) as u8,
+            minute: ((ms_of_day / 60000) % 60) as u8,
+            second: ((ms_of_day / 1000) % 60) as u8,
+            millisecond: (ms_of_day % 1000) as u16,
+        }
+    }
+
+    /// Convert back to milliseconds since the epoch, returning None if any field is out of range.
+    pub fn to_epoch_ms(&self) -> Option<i64> {
+        if self.year.unsigned_abs() > MAX_ABS_YEAR
+            || self.month < 1
+            || self.month > 12
+            || self.day < 1
+            || self.day > days_in_month(self.year, self.month)
+            || self.hour > 23
+            || self.minute > 59
+            || self.second > 59
+            || self.millisecond > 999
+        {
+            return None;
+        }
+        // The day itself can be just outside i64 milliseconds when the time of day brings it back in
+        // range, e.g. for i64::MIN, so the sum is done in i128.
+        let ms = (days_from_civil(self.year, self.month, self.day) as i128) * (MS_PER_DAY as i128)
+            + (self.hour as i128) * 3600000
+            + (self.minute as i128) * 60000
+            + (self.second as i128) * 1000
+            + (self.millisecond as i128);
+        i64::try_from(ms).ok()
+    }
+}
+
+/// Format milliseconds since the epoch as an RFC 3339 UTC timestamp, e.g. "2023-12-18T09:30:00.000Z".
+pub fn to_rfc3339(ms: i64) -> String {
+    format_rfc3339(CivilTime::from_epoch_ms(ms), 0)
+}
+
+/// Format milliseconds since the epoch as an RFC 3339 timestamp in a fixed offset from UTC.
+///
+/// An offset of zero is written as "Z". Offsets are truncated to whole minutes and must be less
+/// than 24 hours in either direction. An error is returned if applying the offset takes the local
+/// time outside the range of i64 milliseconds.
+pub fn to_rfc3339_with_offset(ms: i64, offset_minutes: i32) -> Result<String, InvalidParameterError> {
+    let offset_minutes = offset_minutes.clamp(-1439, 1439);
+    let local = ms
+        .checked_add((offset_minutes as i64) * 60000)
+        .ok_or(InvalidParameterError("timestamp out of range for offset"))?;
+    Ok(format_rfc3339(CivilTime::from_epoch_ms(local), offset_minutes))
+}
+
+fn format_rfc3339(t: CivilTime, offset_minutes: i32) -> String {
+    let mut s = String::with_capacity(32);
+    if (0..=9999).contains(&t.year) {
+        let _ = write!(s, "{:04}", t.year);
+    } else {
+        // RFC 3339 only covers years 0000-9999, so this follows the ISO 8601 expanded year form.
+        let _ = write!(s, "{:+05}", t.year);
+    }
+    let _ = write!(
+        s,
+        "-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}",
+        t.month, t.day, t.hour, t.minute, t.second, t.millisecond
+    );
+    if offset_minutes == 0 {
+        s.push('Z');
+    } else {
+        let _ = write!(
+            s,
+            "{}{:02}:{:02}",
+            if offset_minutes < 0 {
+                '-'
+            } else {
+                '+'
+            },
+            offset_minutes.unsigned_abs() / 60,
+            offset_minutes.unsigned_abs() % 60
+        );
+    }
+    s
+}
+
+fn parse_digits(s: &[u8], n: usize) -> Result<(i64, &[u8]), InvalidParameterError> {
+    if s.len() < n || !s[..n].iter().all(|c| c.is_ascii_digit()) {
+        return Err(InvalidParameterError("invalid RFC 3339 timestamp: expected digits"));
+    }
+    Ok((s[..n].iter().fold(0, |v, c| v * 10 + (*c - b'0') as i64), &s[n..]))
+}
+
+fn expect(s: &[u8], c: u8) -> Result<&[u8], InvalidParameterError> {
+    if s.first().is_some_and(|f| f.eq_ignore_ascii_case(&c)) {
+        Ok(&s[1..])
+    } else {
+        Err(InvalidParameterError(
+            "invalid RFC 3339 timestamp: unexpected character",
+        ))
+    }
+}
+
+/// Parse an RFC 3339 timestamp into milliseconds since the epoch.
+///
+/// Fractional seconds beyond milliseconds are truncated. A space is accepted in place of 'T' as
+/// RFC 3339 permits, as are expanded years with a leading sign as written by to_rfc3339().
+pub fn from_rfc3339(s: &str) -> Result<i64, InvalidParameterError> {
+    let s = s.trim().as_bytes();
+    let (year, s) = match s.first() {
+        Some(b'+') | Some(b'-') => {
+            let digits = s[1..].iter().take_while(|c| c.is_ascii_digit()).count();
+            if !(4..=12).contains(&digits) {
+                return Err(InvalidParameterError("invalid RFC 3339 timestamp: bad year"));
+            }
+            let (y, rest) = parse_digits(&s[1..], digits)?;
+            (
+                if s[0] == b'-' {
+                    -y
+                } else {
+                    y
+                },
+                rest,
+            )
+        }
+        _ => parse_digits(s, 4)?,
+    };
+    let (month, s) = parse_digits(expect(s, b'-')?, 2)?;
+    let (day, s) = parse_digits(expect(s, b'-')?, 2)?;
+    let s = if s.first() == Some(&b' ') {
+        &s[1..]
+    } else {
+        expect(s, b'T')?
+    };
+    let (hour, s) = parse_digits(s, 2)?;
+    let (minute, s) = parse_digits(expect(s, b':')?, 2)?;
+    let (second, mut s) = parse_digits(expect(s, b':')?, 2)?;
+
+    let mut millisecond = 0;
+    if s.first() == Some(&b'.') {
+        let digits = s[1..].iter().take_while(|c| c.is_ascii_digit()).count();
+        if digits == 0 {
+            return Err(InvalidParameterError("invalid RFC 3339 timestamp: empty fraction"));
+        }
+        for i in 0..3 {
+            millisecond = millisecond * 10 + s.get(1 + i).filter(|_| i < digits).map_or(0, |c| (*c - b'0') as i64);
+        }
+        s = &s[1 + digits..];
+    }
+
+    let offset_ms = match s.first() {
+        Some(b'Z') | Some(b'z') => {
+            s = &s[1..];
+            0
+        }
+        Some(b'+') | Some(b'-') => {
+            let sign = if s[0] == b'-' {
+                -1
+            } else {
+                1
+            };
+            let (oh, rest) = parse_digits(&s[1..], 2)?;
+            let (om, rest) = parse_digits(expect(rest, b':')?, 2)?;
+            if oh > 23 || om > 59 {
+                return Err(InvalidParameterError("invalid RFC 3339 timestamp: bad offset"));
+            }
+            s = rest;
+            sign * (oh * 3600000 + om * 60000)
+        }
+        _ => return Err(InvalidParameterError("invalid RFC 3339 timestamp: missing offset")),
+    };
+    if !s.is_empty() {
+        return Err(InvalidParameterError("invalid RFC 3339 timestamp: trailing characters"));
+    }
+
+    // A leap second is accepted and folded into the following second, since epoch time has no way to represent it.
+    // Leap seconds are only ever inserted at the end of a UTC day, so :60 is rejected anywhere else.
+    let leap = second == 60;
+    let t = CivilTime {
+        year,
+        month: month as u8,
+        day: day as u8,
+        hour: hour as u8,
+        minute: minute as u8,
+        second: if leap {
+            59
+        } else {
+            second as u8
+        },
+        millisecond: millisecond as u16,
+    };
+    let ms = t
+        .to_epoch_ms()
+        .and_then(|ms| ms.checked_sub(offset_ms))
+        .ok_or(InvalidParameterError("invalid RFC 3339 timestamp: field out of range"))?;
+    if leap && ms.rem_euclid(MS_PER_DAY) / 60000 != 23 * 60 + 59 {
+        return Err(InvalidParameterError(
+            "invalid RFC 3339 timestamp: leap second not at 23:59 UTC",
+        ));
+    }
+    ms.checked_add(leap as i64 * 1000)
+        .ok_or(InvalidParameterError("invalid RFC 3339 timestamp: field out of range"))
+}
+
+/// Serde adapter for i64 millisecond timestamps, for use with #[serde(with = "...")].
+///
+/// Human readable formats get an RFC 3339 UTC string and binary formats get the raw integer, in the
+/// same way Blob and InetAddress switch representations. Integers are also accepted when reading
+/// human readable formats so existing data keeps working.
+pub mod rfc3339_ms {
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(ms: &i64, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(super::to_rfc3339(*ms).as_str())
+        } else {
+            serializer.serialize_i64(*ms)
+        }
+    }
+
+    struct TimestampVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for TimestampVisitor {
+        type Value = i64;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("RFC 3339 timestamp or milliseconds since epoch")
+        }
+
+        fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            super::from_rfc3339(v).map_err(|e| E::custom(e.to_string()))
+        }
+
+        fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Self::Value, E> {
+            Ok(v)
+        }
+
+        fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Self::Value, E> {
+            i64::try_from(v).map_err(|_| E::custom("timestamp out of range"))
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(TimestampVisitor)
+        } else {
+            deserializer.deserialize_i64(TimestampVisitor)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    #[test]
+    fn civil_round_trip() {
+        assert_eq!(
+            CivilTime::from_epoch_ms(0),
+            CivilTime { year: 1970, month: 1, day: 1, ..Default::default() }
+        );
+        assert_eq!(
+            CivilTime::from_epoch_ms(-1),
+            CivilTime {
+                year: 1969,
+                month: 12,
+                day: 31,
+                hour: 23,
+                minute: 59,
+                second: 59,
+                millisecond: 999
+            }
+        );
+        let mut x = 1_u64;
+        for _ in 0..10000 {
+            x = x.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let ms = (x >> 8) as i64 - (1 << 55);
+            assert_eq!(CivilTime::from_epoch_ms(ms).to_epoch_ms(), Some(ms));
+            assert_eq!(from_rfc3339(&to_rfc3339(ms)).unwrap(), ms);
+        }
+        assert!(CivilTime { year: 2023, month: 2, day: 29, ..Default::default() }
+            .to_epoch_ms()
+            .is_none());
+        assert!(CivilTime { year: 2024, month: 2, day: 29, ..Default::default() }
+            .to_epoch_ms()
+            .is_some());
+
+        for ms in [i64::MIN, i64::MAX] {
+            assert_eq!(CivilTime::from_epoch_ms(ms).to_epoch_ms(), Some(ms));
+        }
+        for year in [i64::MIN, i64::MAX, 300_000_001, 292_278_995, -292_275_056] {
+            assert!(CivilTime { year, month: 1, day: 1, ..Default::default() }
+                .to_epoch_ms()
+                .is_none());
+        }
+    }
+
+    #[test]
+    fn format_and_parse() {
+        assert_eq!(to_rfc3339(1702891800123), "2023-12-18T09:30:00.123Z");
+        assert_eq!(
+            to_rfc3339_with_offset(1702891800123, -300).unwrap(),
+            "2023-12-18T04:30:00.123-05:00"
+        );
+        assert_eq!(
+            to_rfc3339_with_offset(1702891800123, 330).unwrap(),
+            "2023-12-18T15:00:00.123+05:30"
+        );
+        assert_eq!(from_rfc3339("2023-12-18T04:30:00.123-05:00").unwrap(), 1702891800123);
+        assert_eq!(from_rfc3339("2023-12-18 09:30:00z").unwrap(), 1702891800000);
+        assert_eq!(from_rfc3339("2023-12-18t09:30:00.1239999Z").unwrap(), 1702891800123);
+        assert_eq!(
+            from_rfc3339("2016-12-31T23:59:60Z").unwrap(),
+            from_rfc3339("2017-01-01T00:00:00Z").unwrap()
+        );
+        assert_eq!(
+            from_rfc3339("2016-12-31T18:59:60-05:00").unwrap(),
+            from_rfc3339("2017-01-01T00:00:00Z").unwrap()
+        );
+        assert!(to_rfc3339_with_offset(i64::MAX, 60).is_err());
+        assert!(to_rfc3339_with_offset(i64::MIN, -60).is_err());
+        for bad in [
+            "",
+            "2023-12-18",
+            "2023-12-18T09:30:00",
+            "2023-13-18T09:30:00Z",
+            "2023-12-18T24:00:00Z",
+            "2023-12-18T09:30:00.Z",
+            "2023-12-18T09:30:00+0500",
+            "2023-12-18T09:30:00Zjunk",
+            "2023-12-18T09:30:60Z",
+            "2016-12-31T23:59:60+01:00",
+        ] {
+            assert!(from_rfc3339(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn serde_adapter() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Status {
+            #[serde(with = "rfc3339_ms")]
+            timestamp: i64,
+        }
+        let s = Status { timestamp: 1702891800123 };
+        let json = serde_json::to_string(&s).unwrap();
+        assert_eq!(json, "{\"timestamp\":\"2023-12-18T09:30:00.123Z\"}");
+        assert_eq!(serde_json::from_str::<Status>(&json).unwrap(), s);
+        assert_eq!(
+            serde_json::from_str::<Status>("{\"timestamp\":1702891800123}").unwrap(),
+            s
+        );
+    }
+}
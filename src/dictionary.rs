@@ -6,21 +6,251 @@
  * https://www.zerotier.com/
  */
 
+mod binary;
+mod borrowed;
+mod de;
+mod json;
+mod patch;
+mod scope;
+mod ser;
+
 use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{Debug, Display};
 use std::io::Write;
+use std::path::Path;
+
+pub use binary::{is_binary, BINARY_FORMAT_MARKER};
+pub use borrowed::DictionaryRef;
+pub use de::{from_bytes, from_dictionary};
+pub use json::BASE64_TAG;
+pub use patch::{diff, DictionaryPatch, MergePolicy};
+pub use scope::DictionaryScope;
+pub use ser::{to_bytes, to_dictionary};
 
-use crate::hex;
+use crate::blob::Blob;
+use crate::error::{DictionaryLimit, DictionaryLimitError, DictionaryParseError};
+use crate::inetaddress::InetAddress;
+use crate::{hex, io, units};
 
 const BOOL_TRUTH: &str = "1tTyY";
 
+/// Error returned by the Dictionary serde data format.
+pub struct SerdeError(pub String);
+
+impl Display for SerdeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SerdeError: {}", self.0)
+    }
+}
+
+impl Debug for SerdeError {
+    #[inline(always)]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        <Self as Display>::fmt(self, f)
+    }
+}
+
+impl Error for SerdeError {}
+
+impl serde::ser::Error for SerdeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+impl serde::de::Error for SerdeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
 /// Dictionary is an extremely simple key=value serialization format.
 ///
 /// It's designed for extreme parsing simplicity and is human readable if keys and values are strings.
 /// It also supports binary keys and values which will be minimally escaped but render the result not
 /// entirely human readable. Keys are serialized in natural sort order so the result can be consistently
 /// checksummed or hashed.
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Dictionary(pub(crate) BTreeMap<String, Vec<u8>>);
+///
+/// Dictionaries from untrusted sources should be parsed with from_bytes_with_limits(), and the same
+/// limits are then enforced by the setters of the resulting dictionary.
+#[derive(Debug, Clone)]
+pub struct Dictionary {
+    pub(crate) entries: BTreeMap<String, Vec<u8>>,
+    limits: DictionaryLimits,
+    size: usize,
+}
+
+/// Limits on the size and shape of a Dictionary to bound memory use with untrusted input.
+///
+/// Sizes are in bytes, with key and value lengths measured unescaped and the total size measured
+/// as serialized in the text format, whichever format the data was parsed from. Parsing also rejects
+/// input that is itself longer than the total size before looking at it.
+///
+/// Nesting depth counts levels of dictionaries and lists stored with set_dictionary() or set_list(),
+/// which write them in the binary format so they can be told apart from other values. Strings that
+/// happen to look like text format dictionaries are not counted. Nested dictionaries stored as text
+/// by other means are not counted either, but get_dictionary() still enforces the limit on them.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DictionaryLimits {
+    pub max_entries: usize,
+    pub max_key_len: usize,
+    pub max_value_len: usize,
+    pub max_total_size: usize,
+    pub max_nesting_depth: usize,
+}
+
+impl DictionaryLimits {
+    pub const UNLIMITED: Self = Self {
+        max_entries: usize::MAX,
+        max_key_len: usize::MAX,
+        max_value_len: usize::MAX,
+        max_total_size: usize::MAX,
+        max_nesting_depth: usize::MAX,
+    };
+
+    /// Conservative limits for dictionaries received from remote peers.
+    pub const UNTRUSTED: Self = Self {
+        max_entries: 1024,
+        max_key_len: 256,
+        max_value_len: 65536,
+        max_total_size: 1048576,
+        max_nesting_depth: 4,
+    };
+
+    /// Get the limits for a dictionary nested one level inside one with these limits.
+    pub fn nested(&self) -> Option<Self> {
+        if self.max_nesting_depth == 0 {
+            None
+        } else {
+            Some(Self { max_nesting_depth: self.max_nesting_depth - 1, ..*self })
+        }
+    }
+}
+
+impl Default for DictionaryLimits {
+    #[inline(always)]
+    fn default() -> Self {
+        Self::UNLIMITED
+    }
+}
+
+/// Typed getters shared by Dictionary and its borrowed views, implemented on top of get_bytes().
+macro_rules! typed_getters {
+    () => {
+        pub fn get_str(&self, k: &str) -> Option<&str> {
+            self.get_bytes(k).and_then(|v| std::str::from_utf8(v).ok())
+        }
+
+        /// Get an unsigned integer in hex. An empty value is zero.
+        pub fn get_u64(&self, k: &str) -> Option<u64> {
+            self.get_str(k).and_then(|s| {
+                if s.is_empty() {
+                    Some(0)
+                } else {
+                    u64::from_str_radix(s, 16).ok()
+                }
+            })
+        }
+
+        /// Get a signed integer in hex with an optional leading '-'. An empty value is zero.
+        pub fn get_i64(&self, k: &str) -> Option<i64> {
+            self.get_str(k).and_then(|s| {
+                if s.is_empty() {
+                    Some(0)
+                } else {
+                    i64::from_str_radix(s, 16).ok()
+                }
+            })
+        }
+
+        pub fn get_bool(&self, k: &str) -> Option<bool> {
+            self.get_bytes(k).map(|v| {
+                v.first()
+                    .map_or(false, |c| $crate::dictionary::BOOL_TRUTH.contains(*c as char))
+            })
+        }
+
+        pub fn get_f64(&self, k: &str) -> Option<f64> {
+            self.get_str(k).and_then(|s| s.parse().ok())
+        }
+
+        pub fn get_inetaddress(&self, k: &str) -> Option<$crate::inetaddress::InetAddress> {
+            self.get_str(k).and_then(|s| s.parse().ok())
+        }
+
+        /// Get a blob, returning None if the value is not exactly L bytes long.
+        pub fn get_blob<const L: usize>(&self, k: &str) -> Option<$crate::blob::Blob<L>> {
+            self.get_bytes(k).and_then(|v| v.try_into().ok())
+        }
+
+        /// Get a nested dictionary stored with set_dictionary().
+        ///
+        /// The result inherits this dictionary's limits with one less level of nesting allowed.
+        pub fn get_dictionary(&self, k: &str) -> Option<$crate::dictionary::Dictionary> {
+            let limits = self.limits().nested()?;
+            self.get_bytes(k)
+                .and_then(|v| $crate::dictionary::Dictionary::from_bytes_with_limits(v, &limits).ok())
+        }
+
+        /// Get a list of values stored with set_list().
+        pub fn get_list(&self, k: &str) -> Option<Vec<Vec<u8>>> {
+            self.get_dictionary(k)
+                .and_then($crate::dictionary::list_from_dictionary)
+        }
+
+        /// Get a human readable duration such as "1h30m" in milliseconds, see units::parse_duration_ms().
+        pub fn get_duration(&self, k: &str) -> Option<i64> {
+            self.get_str(k)
+                .and_then(|s| $crate::units::parse_duration_ms(s).ok())
+        }
+
+        /// Get a human readable byte size such as "256KiB", see units::parse_size().
+        pub fn get_size(&self, k: &str) -> Option<u64> {
+            self.get_str(k).and_then(|s| $crate::units::parse_size(s).ok())
+        }
+    };
+}
+pub(crate) use typed_getters;
+
+/// Encode an unsigned integer in hex without leading zeroes, so zero is an empty value.
+///
+/// This is the encoding set_u64() has always used and must not change, since it determines the
+/// canonical bytes of dictionaries that may be signed or hashed.
+fn u64_value(v: u64) -> Vec<u8> {
+    hex::to_vec_u64(v, true)
+}
+
+/// Encode a signed integer in hex with a leading '-' if negative.
+fn i64_value(v: i64) -> Vec<u8> {
+    let mut b = u64_value(v.unsigned_abs());
+    if v < 0 {
+        b.insert(0, b'-');
+    }
+    b
+}
+
+/// Get the serialized size of a key or value, including escape characters.
+fn escaped_len(b: &[u8]) -> usize {
+    b.len()
+        + b.iter()
+            .filter(|c| matches!(**c, 0 | b'\n' | b'\r' | b'=' | b'\\'))
+            .count()
+}
+
+#[inline(always)]
+fn entry_size(k: &[u8], v: &[u8]) -> usize {
+    escaped_len(k) + escaped_len(v) + 2
+}
+
+/// Decode a list stored as a sub-dictionary with keys "0" through "n-1".
+pub(crate) fn list_from_dictionary(mut d: Dictionary) -> Option<Vec<Vec<u8>>> {
+    let mut l = Vec::with_capacity(d.len());
+    for i in 0..d.len() {
+        l.push(d.entries.remove(i.to_string().as_str())?);
+    }
+    Some(l)
+}
 
 fn write_escaped<W: Write>(mut b: &[u8], w: &mut W) -> std::io::Result<()> {
     while !b.is_empty() {
@@ -65,70 +295,147 @@ fn append_printable(s: &mut String, b: &[u8]) {
 
 impl Dictionary {
     pub fn new() -> Self {
-        Self(BTreeMap::new())
+        Self::with_limits(DictionaryLimits::UNLIMITED)
+    }
+
+    /// Create an empty dictionary whose setters enforce the given limits.
+    pub fn with_limits(limits: DictionaryLimits) -> Self {
+        Self { entries: BTreeMap::new(), limits, size: 0 }
+    }
+
+    /// Create an unlimited dictionary from existing entries.
+    pub(crate) fn from_entries(entries: BTreeMap<String, Vec<u8>>) -> Self {
+        let size = entries.iter().map(|(k, v)| entry_size(k.as_bytes(), v)).sum();
+        Self { entries, limits: DictionaryLimits::UNLIMITED, size }
+    }
+
+    #[inline(always)]
+    pub fn limits(&self) -> &DictionaryLimits {
+        &self.limits
     }
 
     pub fn clear(&mut self) {
-        self.0.clear()
+        self.entries.clear();
+        self.size = 0;
     }
 
     #[inline(always)]
     pub fn len(&self) -> usize {
-        self.0.len()
+        self.entries.len()
     }
 
     #[inline(always)]
     pub fn is_empty(&self) -> bool {
-        self.0.is_empty()
+        self.entries.is_empty()
     }
 
-    pub fn get_str(&self, k: &str) -> Option<&str> {
-        self.0.get(k).and_then(|v| std::str::from_utf8(v.as_slice()).ok())
+    pub fn get_bytes(&self, k: &str) -> Option<&[u8]> {
+        self.entries.get(k).map(|v| v.as_slice())
     }
 
-    pub fn get_bytes(&self, k: &str) -> Option<&[u8]> {
-        self.0.get(k).map(|v| v.as_slice())
+    typed_getters!();
+
+    fn insert(&mut self, k: &str, v: Vec<u8>) -> Result<(), DictionaryLimitError> {
+        if k.len() > self.limits.max_key_len {
+            return Err(DictionaryLimitError(DictionaryLimit::KeyLength));
+        }
+        if v.len() > self.limits.max_value_len {
+            return Err(DictionaryLimitError(DictionaryLimit::ValueLength));
+        }
+        let old_size = match self.entries.get(k) {
+            Some(old) => entry_size(k.as_bytes(), old),
+            None => {
+                if self.entries.len() >= self.limits.max_entries {
+                    return Err(DictionaryLimitError(DictionaryLimit::Entries));
+                }
+                0
+            }
+        };
+        let size = self.size - old_size + entry_size(k.as_bytes(), &v);
+        if size > self.limits.max_total_size {
+            return Err(DictionaryLimitError(DictionaryLimit::TotalSize));
+        }
+        // Checked last since it may have to parse the value.
+        if !borrowed::within_nesting_depth(&v, &self.limits) {
+            return Err(DictionaryLimitError(DictionaryLimit::NestingDepth));
+        }
+        self.size = size;
+        let _ = self.entries.insert(String::from(k), v);
+        Ok(())
+    }
+
+    pub fn set_str(&mut self, k: &str, v: &str) -> Result<(), DictionaryLimitError> {
+        self.insert(k, v.as_bytes().to_vec())
+    }
+
+    pub fn set_u64(&mut self, k: &str, v: u64) -> Result<(), DictionaryLimitError> {
+        self.insert(k, u64_value(v))
+    }
+
+    pub fn set_i64(&mut self, k: &str, v: i64) -> Result<(), DictionaryLimitError> {
+        self.insert(k, i64_value(v))
     }
 
-    pub fn get_u64(&self, k: &str) -> Option<u64> {
-        self.get_str(k).and_then(|s| u64::from_str_radix(s, 16).ok())
+    pub fn set_f64(&mut self, k: &str, v: f64) -> Result<(), DictionaryLimitError> {
+        self.insert(k, v.to_string().into_bytes())
     }
 
-    pub fn get_i64(&self, k: &str) -> Option<i64> {
-        self.get_str(k).and_then(|s| i64::from_str_radix(s, 16).ok())
+    pub fn set_inetaddress(&mut self, k: &str, v: &InetAddress) -> Result<(), DictionaryLimitError> {
+        self.insert(k, v.to_string().into_bytes())
     }
 
-    pub fn get_bool(&self, k: &str) -> Option<bool> {
-        self.0
-            .get(k)
-            .and_then(|v| v.first().map_or(Some(false), |c| Some(BOOL_TRUTH.contains(*c as char))))
+    pub fn set_blob<const L: usize>(&mut self, k: &str, v: &Blob<L>) -> Result<(), DictionaryLimitError> {
+        self.insert(k, v.as_bytes().to_vec())
     }
 
-    pub fn set_str(&mut self, k: &str, v: &str) {
-        let _ = self.0.insert(String::from(k), v.as_bytes().to_vec());
+    /// Store a dictionary as a value in the binary format, which marks it as nested for max_nesting_depth.
+    pub fn set_dictionary(&mut self, k: &str, v: &Dictionary) -> Result<(), DictionaryLimitError> {
+        self.insert(k, v.to_binary_bytes())
     }
 
-    pub fn set_u64(&mut self, k: &str, v: u64) {
-        let _ = self.0.insert(String::from(k), hex::to_vec_u64(v, true));
+    /// Store a list of values as a nested dictionary with keys "0" through "n-1".
+    pub fn set_list<I: IntoIterator>(&mut self, k: &str, v: I) -> Result<(), DictionaryLimitError>
+    where
+        I::Item: AsRef<[u8]>,
+    {
+        let mut d = Dictionary::new();
+        for (i, item) in v.into_iter().enumerate() {
+            d.insert(i.to_string().as_str(), item.as_ref().to_vec())?;
+        }
+        self.set_dictionary(k, &d)
     }
 
-    pub fn set_bytes(&mut self, k: &str, v: Vec<u8>) {
-        let _ = self.0.insert(String::from(k), v);
+    pub fn set_bytes(&mut self, k: &str, v: Vec<u8>) -> Result<(), DictionaryLimitError> {
+        self.insert(k, v)
     }
 
-    pub fn set_bool(&mut self, k: &str, v: bool) {
-        let _ = self.0.insert(
-            String::from(k),
+    pub fn set_bool(&mut self, k: &str, v: bool) -> Result<(), DictionaryLimitError> {
+        self.insert(
+            k,
             vec![if v {
                 b'1'
             } else {
                 b'0'
             }],
-        );
+        )
+    }
+
+    pub fn set_duration(&mut self, k: &str, ms: i64) -> Result<(), DictionaryLimitError> {
+        self.insert(k, units::format_duration_ms(ms).into_bytes())
+    }
+
+    pub fn set_size(&mut self, k: &str, bytes: u64) -> Result<(), DictionaryLimitError> {
+        self.insert(k, units::format_size(bytes).into_bytes())
+    }
+
+    pub fn remove(&mut self, k: &str) -> Option<Vec<u8>> {
+        let v = self.entries.remove(k)?;
+        self.size -= entry_size(k.as_bytes(), &v);
+        Some(v)
     }
 
     pub fn write_to<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
-        for kv in self.0.iter() {
+        for kv in self.entries.iter() {
             write_escaped(kv.0.as_bytes(), w)?;
             w.write_all(&[b'='])?;
             write_escaped(kv.1.as_slice(), w)?;
@@ -138,67 +445,74 @@ impl Dictionary {
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut b: Vec<u8> = Vec::with_capacity(32 * self.0.len());
+        let mut b: Vec<u8> = Vec::with_capacity(self.size);
         let _ = self.write_to(&mut b);
         b
     }
 
-    pub fn from_bytes(b: &[u8]) -> Option<Dictionary> {
-        let mut d = Dictionary::new();
-        let mut kv: [Vec<u8>; 2] = [Vec::new(), Vec::new()];
-        let mut state = 0;
-        let mut escape = false;
-        for c in b {
-            let c = *c;
-            if escape {
-                escape = false;
-                kv[state].push(match c {
-                    b'0' => 0,
-                    b'n' => b'\n',
-                    b'r' => b'\r',
-                    b'e' => b'=',
-                    _ => c, // =, \, and escapes before other characters are unnecessary but not errors
-                });
-            } else if c == b'\\' {
-                escape = true;
-            } else if c == b'=' {
-                if state != 0 {
-                    return None;
-                }
-                state = 1;
-            } else if c == b'\n' {
-                if state != 1 {
-                    return None;
-                }
-                state = 0;
-                if !kv[0].is_empty()
-                    && String::from_utf8(kv[0].clone()).map_or(true, |key| {
-                        d.0.insert(key, kv[1].clone());
-                        false
-                    })
-                {
-                    return None;
-                }
-                kv[0].clear();
-                kv[1].clear();
-            } else if c != b'\r' {
-                kv[state].push(c);
-            }
-        }
-        Some(d)
+    /// Parse a text or binary serialized dictionary, returning an error describing where and why it was rejected.
+    pub fn from_bytes(b: &[u8]) -> Result<Dictionary, DictionaryParseError> {
+        Self::from_bytes_with_limits(b, &DictionaryLimits::UNLIMITED)
+    }
+
+    /// Parse a serialized dictionary, rejecting it as soon as it exceeds any of the given limits.
+    ///
+    /// Nested dictionaries stored with set_dictionary() are parsed as far as needed to check their
+    /// depth, see DictionaryLimits. The result keeps the limits for use by its setters.
+    pub fn from_bytes_with_limits(b: &[u8], limits: &DictionaryLimits) -> Result<Dictionary, DictionaryParseError> {
+        let mut d = Dictionary::with_limits(*limits);
+        d.size = borrowed::parse_entries(b, limits, |k, v| {
+            d.entries.insert(k.into_owned(), v.into_owned()).is_none()
+        })?;
+        Ok(d)
+    }
+
+    /// Load a dictionary from a file of at most io::DEFAULT_FILE_IO_READ_LIMIT bytes.
+    ///
+    /// Files that are too large or were truncated while being written fail with InvalidData.
+    pub fn load<P: AsRef<Path>>(path: P) -> std::io::Result<Dictionary> {
+        Ok(Self::from_bytes(&io::read_all_limit(
+            path,
+            io::DEFAULT_FILE_IO_READ_LIMIT,
+        )?)?)
+    }
+
+    /// Load a dictionary from a file, see from_bytes_with_limits().
+    ///
+    /// No more than the limits' total size or io::DEFAULT_FILE_IO_READ_LIMIT bytes are read, whichever
+    /// is smaller, so unlimited dictionaries are still loaded from a bounded file.
+    pub fn load_with_limits<P: AsRef<Path>>(path: P, limits: &DictionaryLimits) -> std::io::Result<Dictionary> {
+        Ok(Self::from_bytes_with_limits(
+            &io::read_all_limit(path, limits.max_total_size.min(io::DEFAULT_FILE_IO_READ_LIMIT))?,
+            limits,
+        )?)
+    }
+
+    /// Atomically save this dictionary to a file, readable only by the current user on Unix, see io::fs_write_atomic().
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        io::fs_write_atomic(path, self.to_bytes().as_slice())
     }
 
     pub fn iter(&self) -> impl Iterator<Item = (&String, &Vec<u8>)> {
-        self.0.iter()
+        self.entries.iter()
+    }
+}
+
+impl PartialEq for Dictionary {
+    #[inline(always)]
+    fn eq(&self, other: &Self) -> bool {
+        self.entries.eq(&other.entries)
     }
 }
 
+impl Eq for Dictionary {}
+
 impl ToString for Dictionary {
     /// Get the dictionary in an always readable format with non-printable characters replaced by '\xXX'.
     /// This is not a serializable output that can be re-imported. Use write_to() for that.
     fn to_string(&self) -> String {
         let mut s = String::new();
-        for kv in self.0.iter() {
+        for kv in self.entries.iter() {
             append_printable(&mut s, kv.0.as_bytes());
             s.push('=');
             append_printable(&mut s, kv.1.as_slice());
@@ -207,3 +521,181 @@ impl ToString for Dictionary {
         s
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typed_values() {
+        let addr4: InetAddress = "10.1.2.3/9993".parse().unwrap();
+        let addr6: InetAddress = "fd00::1/443".parse().unwrap();
+        let blob = Blob::from([0_u8, b'\n', b'=', b'\\', b'\r', 0xff]);
+        let mut inner = Dictionary::new();
+        inner.set_str("name", "a=b\nc").unwrap();
+        inner.set_list("list", ["x\ny", "", "z="]).unwrap();
+
+        let mut d = Dictionary::new();
+        d.set_u64("u0", 0).unwrap();
+        d.set_u64("umax", u64::MAX).unwrap();
+        d.set_i64("i0", 0).unwrap();
+        d.set_i64("imin", i64::MIN).unwrap();
+        d.set_i64("ineg", -300).unwrap();
+        d.set_f64("f", -1.0e-7).unwrap();
+        d.set_f64("fpi", std::f64::consts::PI).unwrap();
+        d.set_inetaddress("a4", &addr4).unwrap();
+        d.set_inetaddress("a6", &addr6).unwrap();
+        d.set_blob("blob", &blob).unwrap();
+        d.set_dictionary("inner", &inner).unwrap();
+        d.set_list("empty", Vec::<Vec<u8>>::new()).unwrap();
+        d.set_list("many", (0..12).map(|i| vec![i as u8])).unwrap();
+        d.set_duration("dur", 5400250).unwrap();
+        d.set_duration("durneg", -5400250).unwrap();
+        d.set_size("size", 262144).unwrap();
+
+        let b = d.to_bytes();
+        let d2 = Dictionary::from_bytes(&b).unwrap();
+        assert_eq!(d, d2);
+        let r = DictionaryRef::from_bytes(&b).unwrap();
+        // Zero has always been written as an empty value and the wire format must not change.
+        assert_eq!(r.get_bytes("u0"), Some(&b""[..]));
+        assert_eq!(r.get_bytes("i0"), Some(&b""[..]));
+        assert_eq!(r.get_bytes("umax"), Some(&b"ffffffffffffffff"[..]));
+        assert_eq!(d2.get_u64("u0"), Some(0));
+        assert_eq!(r.get_u64("umax"), Some(u64::MAX));
+        assert_eq!(d2.get_i64("i0"), Some(0));
+        assert_eq!(r.get_i64("imin"), Some(i64::MIN));
+        assert_eq!(d2.get_i64("ineg"), Some(-300));
+        assert_eq!(r.get_duration("dur"), Some(5400250));
+        assert_eq!(d2.get_duration("durneg"), Some(-5400250));
+        assert_eq!(r.get_size("size"), Some(262144));
+        assert_eq!(r.get_f64("f"), Some(-1.0e-7));
+        assert_eq!(d2.get_f64("fpi"), Some(std::f64::consts::PI));
+        assert_eq!(r.get_inetaddress("a4"), Some(addr4));
+        assert_eq!(d2.get_inetaddress("a6"), Some(addr6));
+        assert_eq!(r.get_blob::<6>("blob"), Some(blob));
+        assert!(d2.get_blob::<5>("blob").is_none());
+        let inner2 = r.get_dictionary("inner").unwrap();
+        assert_eq!(inner2, inner);
+        assert_eq!(inner2.get_str("name"), Some("a=b\nc"));
+        assert_eq!(
+            inner2.get_list("list"),
+            Some(vec![b"x\ny".to_vec(), Vec::new(), b"z=".to_vec()])
+        );
+        assert_eq!(d2.get_list("empty"), Some(Vec::new()));
+        assert_eq!(r.get_list("many"), Some((0..12).map(|i| vec![i as u8]).collect()));
+        assert!(d2.get_list("f").is_none());
+    }
+
+    #[test]
+    fn limits() {
+        use crate::error::DictionaryParseErrorReason::LimitExceeded;
+
+        let limits = DictionaryLimits {
+            max_entries: 3,
+            max_key_len: 4,
+            max_value_len: 8,
+            max_total_size: 30,
+            max_nesting_depth: 1,
+        };
+        let mut d = Dictionary::from_bytes_with_limits(b"a=1\nb=2\n", &limits).unwrap();
+        assert_eq!(d.limits(), &limits);
+        assert_eq!(
+            d.set_str("long", "123456789"),
+            Err(DictionaryLimitError(DictionaryLimit::ValueLength))
+        );
+        assert_eq!(
+            d.set_str("longer", "1"),
+            Err(DictionaryLimitError(DictionaryLimit::KeyLength))
+        );
+        d.set_str("c", "12345678").unwrap();
+        assert_eq!(d.set_str("d", "1"), Err(DictionaryLimitError(DictionaryLimit::Entries)));
+        d.set_str("a", "12345678").unwrap();
+        assert_eq!(
+            d.set_str("b", "12345678"),
+            Err(DictionaryLimitError(DictionaryLimit::TotalSize))
+        );
+        d.set_str("b", "12345").unwrap();
+        assert_eq!(d.to_bytes().len(), 30);
+        assert_eq!(d.get_str("b"), Some("12345"));
+
+        // Only dictionaries stored with set_dictionary() or set_list() count toward the depth limit.
+        let limits = DictionaryLimits { max_nesting_depth: 1, ..DictionaryLimits::UNLIMITED };
+        let mut inner = Dictionary::new();
+        inner.set_list("l", ["x"]).unwrap();
+        let mut d = Dictionary::with_limits(limits);
+        assert_eq!(
+            d.set_dictionary("i", &inner),
+            Err(DictionaryLimitError(DictionaryLimit::NestingDepth))
+        );
+        assert_eq!(
+            d.set_bytes("i", inner.to_binary_bytes()),
+            Err(DictionaryLimitError(DictionaryLimit::NestingDepth))
+        );
+        d.set_list("l", ["x", "y"]).unwrap();
+        assert_eq!(d.get_list("l"), Some(vec![b"x".to_vec(), b"y".to_vec()]));
+        let parsed = Dictionary::from_bytes_with_limits(&inner.to_bytes(), &limits).unwrap();
+        assert!(parsed.get_dictionary("l").is_some());
+        assert!(parsed.get_dictionary("l").unwrap().get_list("0").is_none());
+
+        let mut outer = Dictionary::new();
+        outer.set_str("a", "1").unwrap();
+        outer.set_dictionary("i", &inner).unwrap();
+        for b in [outer.to_bytes(), outer.to_binary_bytes()] {
+            let e = Dictionary::from_bytes_with_limits(&b, &limits).unwrap_err();
+            assert_eq!((e.offset, e.reason), (6, LimitExceeded(DictionaryLimit::NestingDepth)));
+            assert_eq!(DictionaryRef::from_bytes_with_limits(&b, &limits).unwrap_err(), e);
+            let deeper = DictionaryLimits { max_nesting_depth: 2, ..limits };
+            assert!(Dictionary::from_bytes_with_limits(&b, &deeper).is_ok());
+        }
+
+        // Strings are never counted, even ones that parse as text format dictionaries.
+        let flat = DictionaryLimits { max_nesting_depth: 0, ..DictionaryLimits::UNLIMITED };
+        let mut d = Dictionary::with_limits(flat);
+        d.set_str("s", "x=1\n").unwrap();
+        d.set_bytes("t", outer.to_bytes()).unwrap();
+        assert_eq!(
+            d.set_list("l", ["x"]),
+            Err(DictionaryLimitError(DictionaryLimit::NestingDepth))
+        );
+        assert_eq!(Dictionary::from_bytes_with_limits(&d.to_bytes(), &flat).unwrap(), d);
+        assert!(d.get_dictionary("s").is_none());
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = std::env::temp_dir().join(format!("zt-dictionary-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("identity.dict");
+
+        let mut d = Dictionary::new();
+        d.set_str("name", "node").unwrap();
+        d.set_bytes("secret", vec![0, b'\n', 0xff]).unwrap();
+        d.save(&path).unwrap();
+        d.set_u64("id", 1).unwrap();
+        d.save(&path).unwrap();
+        assert_eq!(Dictionary::load(&path).unwrap(), d);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        let limits = DictionaryLimits { max_total_size: 8, ..DictionaryLimits::UNLIMITED };
+        let e = Dictionary::load_with_limits(&path, &limits).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+        std::fs::write(&path, vec![b'a'; io::DEFAULT_FILE_IO_READ_LIMIT + 1]).unwrap();
+        let e = Dictionary::load_with_limits(&path, &DictionaryLimits::UNLIMITED).unwrap_err();
+        assert!(e.to_string().contains("read limit"));
+        d.save(&path).unwrap();
+
+        let b = d.to_bytes();
+        std::fs::write(&path, &b[..b.len() - 1]).unwrap();
+        let e = Dictionary::load(&path).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+        assert!(e.to_string().contains("trailing data"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
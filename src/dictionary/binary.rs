@@ -0,0 +1,201 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ *
+ * (c) ZeroTier, Inc.
+ * https://www.zerotier.com/
+ */
+
+use std::borrow::Cow;
+use std::io::Write;
+
+use super::borrowed::within_nesting_depth;
+use super::{entry_size, Dictionary, DictionaryLimits};
+use crate::error::{DictionaryLimit, DictionaryParseError, DictionaryParseErrorReason};
+use crate::varint;
+
+/// Marker at the start of binary encoded dictionaries.
+///
+/// Text dictionaries can never start with 0xff since keys must be valid UTF-8. The second byte
+/// is a format version.
+pub const BINARY_FORMAT_MARKER: [u8; 2] = [0xff, 0x01];
+
+/// Check whether serialized dictionary data is in the binary format.
+#[inline(always)]
+pub fn is_binary(b: &[u8]) -> bool {
+    b.starts_with(&BINARY_FORMAT_MARKER)
+}
+
+/// Binary format counterpart of borrowed::parse_entries().
+///
+/// Entries are a varint key length, the key, a varint value length, and the value. Keys must be in
+/// strictly ascending order, so there is exactly one encoding of any dictionary.
+///
+/// The caller has already checked the input length against max_total_size. As with the text format,
+/// the size counted against it here and returned is what the entries take when written as text.
+pub(super) fn parse_binary_entries<'a, F: FnMut(Cow<'a, str>, Cow<'a, [u8]>) -> bool>(
+    b: &'a [u8],
+    limits: &DictionaryLimits,
+    mut f: F,
+) -> Result<usize, DictionaryParseError> {
+    let error = |offset: usize, reason: DictionaryParseErrorReason| DictionaryParseError::at(b, offset, reason);
+    let field = |i: &mut usize| -> Option<&'a [u8]> {
+        let (l, n) = varint::decode_strict(&b[*i..]).ok()?;
+        let start = *i + n;
+        let end = start.checked_add(usize::try_from(l).ok()?)?;
+        if end > b.len() {
+            return None;
+        }
+        *i = end;
+        Some(&b[start..end])
+    };
+
+    let mut prev: Option<&'a str> = None;
+    let mut count = 0;
+    let mut size = 0;
+    let mut i = BINARY_FORMAT_MARKER.len();
+    while i < b.len() {
+        let entry_start = i;
+        let k = field(&mut i).ok_or_else(|| error(entry_start, DictionaryParseErrorReason::InvalidLength))?;
+        let value_start = i;
+        let v = field(&mut i).ok_or_else(|| error(value_start, DictionaryParseErrorReason::InvalidLength))?;
+        let k = std::str::from_utf8(k).map_err(|_| error(entry_start, DictionaryParseErrorReason::NonUtf8Key))?;
+        if k.is_empty() {
+            return Err(error(entry_start, DictionaryParseErrorReason::EmptyKey));
+        }
+        if prev.is_some_and(|p| p >= k) {
+            return Err(error(
+                entry_start,
+                if prev == Some(k) {
+                    DictionaryParseErrorReason::DuplicateKey
+                } else {
+                    DictionaryParseErrorReason::UnsortedKeys
+                },
+            ));
+        }
+        prev = Some(k);
+
+        let limit_error = |limit: DictionaryLimit| error(entry_start, DictionaryParseErrorReason::LimitExceeded(limit));
+        if count >= limits.max_entries {
+            return Err(limit_error(DictionaryLimit::Entries));
+        }
+        if k.len() > limits.max_key_len {
+            return Err(limit_error(DictionaryLimit::KeyLength));
+        }
+        if v.len() > limits.max_value_len {
+            return Err(limit_error(DictionaryLimit::ValueLength));
+        }
+        if !within_nesting_depth(v, limits) {
+            return Err(limit_error(DictionaryLimit::NestingDepth));
+        }
+        size += entry_size(k.as_bytes(), v);
+        if size > limits.max_total_size {
+            return Err(limit_error(DictionaryLimit::TotalSize));
+        }
+        count += 1;
+        if !f(Cow::Borrowed(k), Cow::Borrowed(v)) {
+            return Err(error(entry_start, DictionaryParseErrorReason::DuplicateKey));
+        }
+    }
+    Ok(size)
+}
+
+impl Dictionary {
+    /// Write this dictionary in the binary format, which stores values without escaping them.
+    ///
+    /// Like the text format, entries are written in sorted key order so the output is canonical, and
+    /// an entry with an empty key is left out since parsing either format would drop or reject it.
+    /// Dictionary::from_bytes() accepts either format.
+    pub fn write_binary_to<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        w.write_all(&BINARY_FORMAT_MARKER)?;
+        for (k, v) in self.entries.iter().filter(|(k, _)| !k.is_empty()) {
+            varint::write(w, k.len() as u64)?;
+            w.write_all(k.as_bytes())?;
+            varint::write(w, v.len() as u64)?;
+            w.write_all(v.as_slice())?;
+        }
+        Ok(())
+    }
+
+    pub fn to_binary_bytes(&self) -> Vec<u8> {
+        let mut b: Vec<u8> = Vec::with_capacity(self.size + BINARY_FORMAT_MARKER.len());
+        let _ = self.write_binary_to(&mut b);
+        b
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dictionary::DictionaryRef;
+
+    #[test]
+    fn binary_format() {
+        use crate::error::DictionaryParseErrorReason::*;
+
+        let mut d = Dictionary::new();
+        d.set_str("name", "node").unwrap();
+        d.set_bytes("blob", (0..=255).collect()).unwrap();
+        d.set_bytes("empty", Vec::new()).unwrap();
+        d.set_str(&"k".repeat(200), "long key").unwrap();
+
+        let b = d.to_binary_bytes();
+        assert!(is_binary(&b) && !is_binary(&d.to_bytes()));
+        assert!(b.len() < d.to_bytes().len());
+        assert_eq!(Dictionary::from_bytes(&b).unwrap(), d);
+        assert_eq!(DictionaryRef::from_bytes(&b).unwrap().into_owned(), d);
+        assert_eq!(Dictionary::from_bytes(&b).unwrap().to_binary_bytes(), b);
+        assert!(Dictionary::from_bytes(&BINARY_FORMAT_MARKER).unwrap().is_empty());
+
+        let mut reversed = Dictionary::new();
+        for (k, v) in d.iter().collect::<Vec<_>>().into_iter().rev() {
+            reversed.set_bytes(k, v.clone()).unwrap();
+        }
+        assert_eq!(reversed.to_binary_bytes(), b);
+
+        for (input, offset, reason) in [
+            (&b"\xff\x01\x81a"[..], 4, InvalidLength),
+            (b"\xff\x01\x81a\x85abc", 4, InvalidLength),
+            (b"\xff\x01\x01\x01", 2, InvalidLength),
+            (b"\xff\x01\x01\x80a\x80", 2, InvalidLength),
+            (b"\xff\x01\x81a\x00\x80", 4, InvalidLength),
+            (b"\xff\x01\x81b\x80\x81a\x80", 5, UnsortedKeys),
+            (b"\xff\x01\x81a\x80\x81a\x80", 5, DuplicateKey),
+            (b"\xff\x01\x81\xff\x80", 2, NonUtf8Key),
+            (b"\xff\x01\x80\x80", 2, EmptyKey),
+            (b"\xff\x01\x80\x81x\x81a\x80", 2, EmptyKey),
+        ] {
+            let e = Dictionary::from_bytes(input).unwrap_err();
+            assert_eq!((e.offset, e.reason), (offset, reason));
+            assert_eq!(DictionaryRef::from_bytes(input).unwrap_err(), e);
+        }
+        let mut empty_key = Dictionary::new();
+        empty_key.set_str("", "x").unwrap();
+        empty_key.set_str("a", "1").unwrap();
+        assert_eq!(
+            Dictionary::from_bytes(&empty_key.to_binary_bytes()).unwrap().to_bytes(),
+            Dictionary::from_bytes(&empty_key.to_bytes()).unwrap().to_bytes()
+        );
+        let limits = DictionaryLimits { max_value_len: 255, ..DictionaryLimits::UNLIMITED };
+        assert_eq!(
+            Dictionary::from_bytes_with_limits(&b, &limits).unwrap_err().reason,
+            LimitExceeded(DictionaryLimit::ValueLength)
+        );
+
+        // The raw length is checked up front in both formats, then the size the entries take as text.
+        let limits = DictionaryLimits { max_total_size: b.len() - 1, ..DictionaryLimits::UNLIMITED };
+        let e = Dictionary::from_bytes_with_limits(&b, &limits).unwrap_err();
+        assert_eq!(
+            (e.offset, e.reason),
+            (b.len() - 1, LimitExceeded(DictionaryLimit::TotalSize))
+        );
+        let limits = DictionaryLimits { max_total_size: b.len(), ..DictionaryLimits::UNLIMITED };
+        let e = Dictionary::from_bytes_with_limits(&b, &limits).unwrap_err();
+        assert_eq!(e.reason, LimitExceeded(DictionaryLimit::TotalSize));
+        let limits = DictionaryLimits {
+            max_total_size: d.to_bytes().len(),
+            ..DictionaryLimits::UNLIMITED
+        };
+        assert_eq!(Dictionary::from_bytes_with_limits(&b, &limits).unwrap(), d);
+    }
+}
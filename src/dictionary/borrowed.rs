@@ -0,0 +1,405 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ *
+ * (c) ZeroTier, Inc.
+ * https://www.zerotier.com/
+ */
+
+use std::borrow::Cow;
+use std::collections::btree_map::{BTreeMap, Entry};
+
+use super::binary::{is_binary, parse_binary_entries};
+use super::{entry_size, Dictionary, DictionaryLimits};
+use crate::error::{DictionaryLimit, DictionaryParseError, DictionaryParseErrorReason};
+
+/// Decode an escaped key or value, borrowing it if it contains nothing that needs to be unescaped.
+fn unescape(b: &[u8], needs_unescape: bool) -> Cow<'_, [u8]> {
+    if !needs_unescape {
+        return Cow::Borrowed(b);
+    }
+    let mut v = Vec::with_capacity(b.len());
+    let mut escape = false;
+    for c in b {
+        let c = *c;
+        if escape {
+            escape = false;
+            v.push(match c {
+                b'0' => 0,
+                b'n' => b'\n',
+                b'r' => b'\r',
+                b'e' => b'=',
+                _ => c, // =, \, and escapes before other characters are unnecessary but not errors
+            });
+        } else if c == b'\\' {
+            escape = true;
+        } else if c != b'\r' {
+            v.push(c);
+        }
+    }
+    Cow::Owned(v)
+}
+
+/// Split serialized dictionary data into unescaped keys and values in the order they appear.
+///
+/// Binary format data is detected by its marker and handled by binary::parse_binary_entries().
+///
+/// The callback returns false if a key is a duplicate. Entries with empty keys are skipped. All limits
+/// are checked, and the size the accepted entries will have when serialized is returned.
+pub(super) fn parse_entries<'a, F: FnMut(Cow<'a, str>, Cow<'a, [u8]>) -> bool>(
+    b: &'a [u8],
+    limits: &DictionaryLimits,
+    mut f: F,
+) -> Result<usize, DictionaryParseError> {
+    let limit_error = |offset: usize, limit: DictionaryLimit| {
+        DictionaryParseError::at(b, offset, DictionaryParseErrorReason::LimitExceeded(limit))
+    };
+    // Input longer than the limit is rejected without looking at it in either format.
+    if b.len() > limits.max_total_size {
+        return Err(limit_error(limits.max_total_size, DictionaryLimit::TotalSize));
+    }
+    if is_binary(b) {
+        return parse_binary_entries(b, limits, f);
+    }
+    let mut count = 0;
+    let mut size = 0;
+    let mut i = 0;
+    while i < b.len() {
+        let entry_start = i;
+        let mut fields: [(usize, usize, bool); 2] = [(i, i, false); 2];
+        for (state, field) in fields.iter_mut().enumerate() {
+            field.0 = i;
+            loop {
+                if i >= b.len() {
+                    return Err(DictionaryParseError::at(
+                        b,
+                        entry_start,
+                        DictionaryParseErrorReason::TrailingData,
+                    ));
+                }
+                match b[i] {
+                    b'\\' => {
+                        if i + 1 >= b.len() {
+                            return Err(DictionaryParseError::at(
+                                b,
+                                i,
+                                DictionaryParseErrorReason::TruncatedEscape,
+                            ));
+                        }
+                        field.2 = true;
+                        i += 1;
+                    }
+                    b'\r' => {
+                        field.2 = true;
+                    }
+                    b'=' => {
+                        if state != 0 {
+                            return Err(DictionaryParseError::at(
+                                b,
+                                i,
+                                DictionaryParseErrorReason::UnexpectedEquals,
+                            ));
+                        }
+                        break;
+                    }
+                    b'\n' => {
+                        if state != 1 {
+                            return Err(DictionaryParseError::at(
+                                b,
+                                i,
+                                DictionaryParseErrorReason::MissingEquals,
+                            ));
+                        }
+                        break;
+                    }
+                    _ => {}
+                }
+                i += 1;
+            }
+            field.1 = i;
+            i += 1;
+        }
+        let (ks, ke, kesc) = fields[0];
+        let key = match unescape(&b[ks..ke], kesc) {
+            Cow::Borrowed(k) => std::str::from_utf8(k).ok().map(Cow::Borrowed),
+            Cow::Owned(k) => String::from_utf8(k).ok().map(Cow::Owned),
+        }
+        .ok_or_else(|| DictionaryParseError::at(b, ks, DictionaryParseErrorReason::NonUtf8Key))?;
+        if !key.is_empty() {
+            let (vs, ve, vesc) = fields[1];
+            let value = unescape(&b[vs..ve], vesc);
+            if count >= limits.max_entries {
+                return Err(limit_error(entry_start, DictionaryLimit::Entries));
+            }
+            if key.len() > limits.max_key_len {
+                return Err(limit_error(ks, DictionaryLimit::KeyLength));
+            }
+            if value.len() > limits.max_value_len {
+                return Err(limit_error(vs, DictionaryLimit::ValueLength));
+            }
+            if !within_nesting_depth(&value, limits) {
+                return Err(limit_error(vs, DictionaryLimit::NestingDepth));
+            }
+            size += entry_size(key.as_bytes(), &value);
+            if size > limits.max_total_size {
+                return Err(limit_error(entry_start, DictionaryLimit::TotalSize));
+            }
+            count += 1;
+            if !f(key, value) {
+                return Err(DictionaryParseError::at(
+                    b,
+                    ks,
+                    DictionaryParseErrorReason::DuplicateKey,
+                ));
+            }
+        }
+    }
+    Ok(size)
+}
+
+/// Get how many levels of dictionaries are nested in a value, or None if that's more than max_depth.
+///
+/// Only values in the binary format, which is how set_dictionary() and set_list() store them, count
+/// as nested dictionaries. Recursion stops once max_depth is exceeded.
+fn nesting_depth(v: &[u8], max_depth: usize) -> Option<usize> {
+    if !is_binary(v) {
+        return Some(0);
+    }
+    let mut depth = 0;
+    let mut too_deep = max_depth == 0;
+    let is_dictionary = parse_binary_entries(v, &DictionaryLimits::UNLIMITED, |_, v| {
+        if !too_deep {
+            match nesting_depth(&v, max_depth - 1) {
+                Some(d) => depth = depth.max(d),
+                None => too_deep = true,
+            }
+        }
+        true
+    })
+    .is_ok();
+    if !is_dictionary {
+        Some(0)
+    } else if too_deep {
+        None
+    } else {
+        Some(depth + 1)
+    }
+}
+
+/// Check a value against max_nesting_depth, which is skipped entirely if the depth is unlimited.
+pub(super) fn within_nesting_depth(v: &[u8], limits: &DictionaryLimits) -> bool {
+    limits.max_nesting_depth == usize::MAX || nesting_depth(v, limits.max_nesting_depth).is_some()
+}
+
+/// A read-only dictionary that borrows keys and values from the buffer it was parsed from.
+///
+/// Only keys and values that contain escape sequences are copied. This is cheaper than Dictionary
+/// for data that is parsed often and only read, and it can be converted with into_owned() if needed.
+/// Entries are held in sorted key order.
+#[derive(Debug, Clone, Default)]
+pub struct DictionaryRef<'a> {
+    pub(super) entries: Vec<(Cow<'a, str>, Cow<'a, [u8]>)>,
+    limits: DictionaryLimits,
+}
+
+impl<'a> DictionaryRef<'a> {
+    /// Parse serialized dictionary data, accepting exactly what Dictionary::from_bytes() accepts.
+    #[inline(always)]
+    pub fn from_bytes(b: &'a [u8]) -> Result<Self, DictionaryParseError> {
+        Self::from_bytes_with_limits(b, &DictionaryLimits::UNLIMITED)
+    }
+
+    /// Parse serialized dictionary data, see Dictionary::from_bytes_with_limits().
+    pub fn from_bytes_with_limits(b: &'a [u8], limits: &DictionaryLimits) -> Result<Self, DictionaryParseError> {
+        fn insert_new<'a>(m: &mut BTreeMap<Cow<'a, str>, Cow<'a, [u8]>>, k: Cow<'a, str>, v: Cow<'a, [u8]>) -> bool {
+            match m.entry(k) {
+                Entry::Vacant(e) => {
+                    let _ = e.insert(v);
+                    true
+                }
+                Entry::Occupied(_) => false,
+            }
+        }
+
+        // Canonical input is already sorted and just gets appended. Anything out of order switches
+        // to a map so that hostile unsorted input can't make parsing quadratic.
+        let mut entries: Vec<(Cow<'a, str>, Cow<'a, [u8]>)> = Vec::new();
+        let mut unsorted: Option<BTreeMap<Cow<'a, str>, Cow<'a, [u8]>>> = None;
+        parse_entries(b, limits, |k, v| {
+            if let Some(m) = unsorted.as_mut() {
+                insert_new(m, k, v)
+            } else if entries.last().is_none_or(|last| last.0 < k) {
+                entries.push((k, v));
+                true
+            } else {
+                insert_new(unsorted.insert(entries.drain(..).collect()), k, v)
+            }
+        })?;
+        if let Some(m) = unsorted {
+            entries = m.into_iter().collect();
+        }
+        Ok(Self { entries, limits: *limits })
+    }
+
+    #[inline(always)]
+    pub fn limits(&self) -> &DictionaryLimits {
+        &self.limits
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get_bytes(&self, k: &str) -> Option<&[u8]> {
+        self.entries
+            .binary_search_by(|e| e.0.as_ref().cmp(k))
+            .ok()
+            .map(|i| self.entries[i].1.as_ref())
+    }
+
+    super::typed_getters!();
+
+    /// Iterate over entries in sorted key order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.entries.iter().map(|(k, v)| (k.as_ref(), v.as_ref()))
+    }
+
+    /// Copy all borrowed keys and values into a new Dictionary with the same limits.
+    pub fn into_owned(self) -> Dictionary {
+        let mut d = Dictionary::from_entries(
+            self.entries
+                .into_iter()
+                .map(|(k, v)| (k.into_owned(), v.into_owned()))
+                .collect(),
+        );
+        d.limits = self.limits;
+        d
+    }
+}
+
+impl<'a> PartialEq for DictionaryRef<'a> {
+    #[inline(always)]
+    fn eq(&self, other: &Self) -> bool {
+        self.entries.eq(&other.entries)
+    }
+}
+
+impl<'a> Eq for DictionaryRef<'a> {}
+
+impl<'a> From<DictionaryRef<'a>> for Dictionary {
+    #[inline(always)]
+    fn from(d: DictionaryRef<'a>) -> Self {
+        d.into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn borrowed_parser() {
+        let mut d = Dictionary::new();
+        d.set_str("plain", "value").unwrap();
+        d.set_bytes("escaped", vec![0, b'\n', b'=', b'\\', b'\r', 1]).unwrap();
+        d.set_str("key\nwith=escapes", "x").unwrap();
+        d.set_u64("id", 0xabcdef).unwrap();
+        d.set_bool("flag", true).unwrap();
+        let b = d.to_bytes();
+        let r = DictionaryRef::from_bytes(&b).unwrap();
+        assert_eq!(r.len(), d.len());
+        assert!(r
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_vec()))
+            .eq(d.iter().map(|(k, v)| (k.clone(), v.clone()))));
+        assert!(matches!(
+            r.entries.iter().find(|e| e.0 == "plain").unwrap().1,
+            Cow::Borrowed(_)
+        ));
+        assert!(matches!(
+            r.entries.iter().find(|e| e.0 == "escaped").unwrap().1,
+            Cow::Owned(_)
+        ));
+        assert_eq!(r.get_str("plain"), Some("value"));
+        assert_eq!(r.get_u64("id"), Some(0xabcdef));
+        assert_eq!(r.get_bool("flag"), Some(true));
+        assert_eq!(r.get_str("key\nwith=escapes"), Some("x"));
+        assert_eq!(r.into_owned(), d);
+
+        let r = DictionaryRef::from_bytes(b"c=3\nb=2\r\na=1\n=skipped\n").unwrap();
+        assert!(r
+            .iter()
+            .eq([("a", &b"1"[..]), ("b", &b"2"[..]), ("c", &b"3"[..])].into_iter()));
+        assert!(DictionaryRef::from_bytes(b"a=b=c\n").is_err());
+        assert!(DictionaryRef::from_bytes(b"a\n").is_err());
+
+        // Reverse sorted input goes through the map fallback and must still match Dictionary.
+        let mut reversed = Vec::new();
+        for i in (0..5000).rev() {
+            reversed.extend_from_slice(format!("k{:05}={}\n", i, i).as_bytes());
+        }
+        let r = DictionaryRef::from_bytes(&reversed).unwrap();
+        assert_eq!(r.len(), 5000);
+        assert!(r.iter().map(|(k, _)| k).is_sorted());
+        assert_eq!(r.into_owned(), Dictionary::from_bytes(&reversed).unwrap());
+        reversed.extend_from_slice(b"k02500=dup\n");
+        assert_eq!(
+            DictionaryRef::from_bytes(&reversed).unwrap_err(),
+            Dictionary::from_bytes(&reversed).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        use crate::error::DictionaryParseErrorReason::*;
+        for (input, offset, line, reason) in [
+            (&b"a=1\nb=2=3\n"[..], 7, 2, UnexpectedEquals),
+            (b"a=1\nb\n", 5, 2, MissingEquals),
+            (b"a=1\n\xff=2\n", 4, 2, NonUtf8Key),
+            (b"b=1\na=1\nb=2\n", 8, 3, DuplicateKey),
+            (b"a=1\nb=2\\", 7, 2, TruncatedEscape),
+            (b"a=1\nb=2", 4, 2, TrailingData),
+        ] {
+            let e = Dictionary::from_bytes(input).unwrap_err();
+            assert_eq!((e.offset, e.line, e.reason), (offset, line, reason));
+            assert_eq!(DictionaryRef::from_bytes(input).unwrap_err(), e);
+        }
+        assert!(Dictionary::from_bytes(b"").unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_limits() {
+        use crate::error::DictionaryParseErrorReason::LimitExceeded;
+
+        let limits = DictionaryLimits {
+            max_entries: 3,
+            max_key_len: 4,
+            max_value_len: 8,
+            max_total_size: 30,
+            max_nesting_depth: 1,
+        };
+        for (input, offset, limit) in [
+            (&b"a=1\nb=2\nc=3\nd=4\n"[..], 12, DictionaryLimit::Entries),
+            (b"a=1\nlonger=2\n", 4, DictionaryLimit::KeyLength),
+            (b"a=1\nb=123456789\n", 6, DictionaryLimit::ValueLength),
+            (&[b'a'; 31], 30, DictionaryLimit::TotalSize),
+            // Unescaped NULs are accepted but take two bytes each when serialized again.
+            (
+                b"a=\0\0\0\0\0\0\0\0\nb=\0\0\0\0\0\0\0\0\n",
+                11,
+                DictionaryLimit::TotalSize,
+            ),
+        ] {
+            let e = Dictionary::from_bytes_with_limits(input, &limits).unwrap_err();
+            assert_eq!((e.offset, e.reason), (offset, LimitExceeded(limit)));
+            assert_eq!(DictionaryRef::from_bytes_with_limits(input, &limits).unwrap_err(), e);
+            assert!(Dictionary::from_bytes(input).is_ok() || limit == DictionaryLimit::TotalSize);
+        }
+    }
+}
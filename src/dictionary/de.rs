@@ -0,0 +1,628 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ *
+ * (c) ZeroTier, Inc.
+ * https://www.zerotier.com/
+ */
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound;
+
+use serde::de::value::BorrowedStrDeserializer;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, Visitor};
+use serde::Deserialize;
+
+use super::{Dictionary, SerdeError, BOOL_TRUTH};
+
+/// Maximum number of None elements a sequence may have, since they take no space when serialized.
+const MAX_SEQUENCE_GAP: usize = 65536;
+
+/// Deserialize a value from a Dictionary, borrowing strings and byte arrays from it where possible.
+///
+/// This is the inverse of to_dictionary(). Keys that don't correspond to a field are ignored
+/// unless the type uses #[serde(deny_unknown_fields)].
+pub fn from_dictionary<'de, T: Deserialize<'de>>(dict: &'de Dictionary) -> Result<T, SerdeError> {
+    T::deserialize(ValueDeserializer { dict: &dict.entries, key: String::new() })
+}
+
+/// Deserialize a value from the Dictionary wire format, see from_dictionary().
+pub fn from_bytes<T: DeserializeOwned>(b: &[u8]) -> Result<T, SerdeError> {
+    let d = Dictionary::from_bytes(b).map_err(|e| SerdeError(e.to_string()))?;
+    from_dictionary(&d)
+}
+
+fn child_key(key: &str, name: &str) -> String {
+    if key.is_empty() {
+        String::from(name)
+    } else {
+        let mut k = String::with_capacity(key.len() + 1 + name.len());
+        k.push_str(key);
+        k.push('.');
+        k.push_str(name);
+        k
+    }
+}
+
+pub(super) struct ValueDeserializer<'de> {
+    pub(super) dict: &'de BTreeMap<String, Vec<u8>>,
+    pub(super) key: String,
+}
+
+impl<'de> ValueDeserializer<'de> {
+    fn child(&self, name: &str) -> Self {
+        Self { dict: self.dict, key: child_key(&self.key, name) }
+    }
+
+    /// Iterate over all entries nested under this key, returning each key with this key's prefix removed.
+    fn children(&self) -> impl Iterator<Item = (&'de str, &'de Vec<u8>)> + '_ {
+        let prefix = if self.key.is_empty() {
+            String::new()
+        } else {
+            child_key(&self.key, "")
+        };
+        let prefix_len = prefix.len();
+        self.dict
+            .range::<str, _>((Bound::Included(prefix.as_str()), Bound::Unbounded))
+            .take_while(move |(k, _)| k.starts_with(prefix.as_str()))
+            .map(move |(k, v)| (&k[prefix_len..], v))
+    }
+
+    fn has_children(&self) -> bool {
+        self.children().next().is_some()
+    }
+
+    fn exists(&self) -> bool {
+        self.dict.contains_key(&self.key) || self.has_children()
+    }
+
+    fn child_names(&self) -> BTreeSet<&'de str> {
+        self.children().map(|(k, _)| k.split('.').next().unwrap_or(k)).collect()
+    }
+
+    fn missing(&self) -> SerdeError {
+        SerdeError(format!("missing value for key '{}'", self.key))
+    }
+
+    fn bytes(&self) -> Result<&'de [u8], SerdeError> {
+        self.dict
+            .get(&self.key)
+            .map(|v| v.as_slice())
+            .ok_or_else(|| self.missing())
+    }
+
+    fn str(&self) -> Result<&'de str, SerdeError> {
+        std::str::from_utf8(self.bytes()?).map_err(|_| SerdeError(format!("value for key '{}' is not UTF-8", self.key)))
+    }
+
+    fn invalid(&self, what: &str) -> SerdeError {
+        SerdeError(format!("value for key '{}' is not a valid {}", self.key, what))
+    }
+
+    fn parse_u128(&self) -> Result<u128, SerdeError> {
+        let s = self.str()?.trim();
+        if s.is_empty() {
+            Ok(0)
+        } else {
+            u128::from_str_radix(s, 16).map_err(|_| self.invalid("integer"))
+        }
+    }
+
+    fn parse_i128(&self) -> Result<i128, SerdeError> {
+        let s = self.str()?.trim();
+        let (negative, s) = s.strip_prefix('-').map_or((false, s), |s| (true, s));
+        let v = if s.is_empty() {
+            0
+        } else {
+            u128::from_str_radix(s, 16).map_err(|_| self.invalid("integer"))?
+        };
+        if negative {
+            0_i128.checked_sub_unsigned(v)
+        } else {
+            i128::try_from(v).ok()
+        }
+        .ok_or_else(|| self.invalid("integer"))
+    }
+
+    fn parse_unsigned<T: TryFrom<u128>>(&self) -> Result<T, SerdeError> {
+        T::try_from(self.parse_u128()?).map_err(|_| self.invalid("integer"))
+    }
+
+    fn parse_signed<T: TryFrom<i128>>(&self) -> Result<T, SerdeError> {
+        T::try_from(self.parse_i128()?).map_err(|_| self.invalid("integer"))
+    }
+
+    /// Get the length of a sequence from its highest index and the length written after a trailing None.
+    fn sequence_len(&self) -> Result<usize, SerdeError> {
+        let mut present = 0_usize;
+        let mut len = 0_usize;
+        for i in self
+            .child_names()
+            .into_iter()
+            .filter_map(|name| name.parse::<usize>().ok())
+        {
+            present += 1;
+            len = len.max(i.saturating_add(1));
+        }
+        if self.dict.get(&self.key).is_some_and(|v| !v.is_empty()) {
+            len = len.max(self.parse_unsigned()?);
+        }
+        if len.saturating_sub(present) > MAX_SEQUENCE_GAP {
+            return Err(SerdeError(format!(
+                "too many missing elements in sequence '{}'",
+                self.key
+            )));
+        }
+        Ok(len)
+    }
+
+    fn parse_float<T: std::str::FromStr>(&self) -> Result<T, SerdeError> {
+        self.str()?.trim().parse::<T>().map_err(|_| self.invalid("number"))
+    }
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer<'de> {
+    type Error = SerdeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        if let Some(v) = self.dict.get(&self.key) {
+            if !self.has_children() {
+                return match std::str::from_utf8(v) {
+                    Ok(s) => visitor.visit_borrowed_str(s),
+                    Err(_) => visitor.visit_borrowed_bytes(v),
+                };
+            }
+        }
+        if self.has_children() {
+            self.deserialize_map(visitor)
+        } else {
+            Err(self.missing())
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_bool(self.bytes()?.first().is_some_and(|c| BOOL_TRUTH.contains(*c as char)))
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_i8(self.parse_signed()?)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_i16(self.parse_signed()?)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_i32(self.parse_signed()?)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_i64(self.parse_signed()?)
+    }
+
+    fn deserialize_i128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_i128(self.parse_i128()?)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_u8(self.parse_unsigned()?)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_u16(self.parse_unsigned()?)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_u32(self.parse_unsigned()?)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_u64(self.parse_unsigned()?)
+    }
+
+    fn deserialize_u128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_u128(self.parse_u128()?)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_f32(self.parse_float()?)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_f64(self.parse_float()?)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        let mut chars = self.str()?.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => visitor.visit_char(c),
+            _ => Err(self.invalid("char")),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_borrowed_str(self.str()?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_borrowed_bytes(self.bytes()?)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        if self.exists() {
+            visitor.visit_some(self)
+        } else {
+            visitor.visit_none()
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        let len = self.sequence_len()?;
+        visitor.visit_seq(SeqAccess { parent: self, index: 0, len })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        let names = self.child_names().into_iter().collect::<Vec<_>>().into_iter();
+        visitor.visit_map(MapAccess { parent: self, names, current: None })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        if !self.has_children() {
+            return visitor.visit_enum(BorrowedStrDeserializer::new(self.str()?));
+        }
+        let names = self.child_names();
+        if names.len() != 1 {
+            return Err(SerdeError(format!(
+                "key '{}' must contain exactly one enum variant",
+                self.key
+            )));
+        }
+        let variant = names.into_iter().next().unwrap();
+        visitor.visit_enum(EnumAccess { de: self.child(variant), variant })
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_unit()
+    }
+}
+
+/// Elements missing below the sequence length are None, or an error for types that aren't Options.
+struct SeqAccess<'de> {
+    parent: ValueDeserializer<'de>,
+    index: usize,
+    len: usize,
+}
+
+impl<'de> de::SeqAccess<'de> for SeqAccess<'de> {
+    type Error = SerdeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, SerdeError> {
+        if self.index >= self.len {
+            return Ok(None);
+        }
+        let element = self.parent.child(self.index.to_string().as_str());
+        self.index += 1;
+        seed.deserialize(element).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.len - self.index)
+    }
+}
+
+struct MapAccess<'de> {
+    parent: ValueDeserializer<'de>,
+    names: std::vec::IntoIter<&'de str>,
+    current: Option<&'de str>,
+}
+
+impl<'de> de::MapAccess<'de> for MapAccess<'de> {
+    type Error = SerdeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, SerdeError> {
+        if let Some(name) = self.names.next() {
+            self.current = Some(name);
+            seed.deserialize(KeyDeserializer(name)).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, SerdeError> {
+        let name = self
+            .current
+            .take()
+            .ok_or_else(|| SerdeError(String::from("map value requested before key")))?;
+        seed.deserialize(self.parent.child(name))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.names.len())
+    }
+}
+
+struct EnumAccess<'de> {
+    de: ValueDeserializer<'de>,
+    variant: &'de str,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccess<'de> {
+    type Error = SerdeError;
+    type Variant = ValueDeserializer<'de>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self::Variant), SerdeError> {
+        let v = seed.deserialize(BorrowedStrDeserializer::<SerdeError>::new(self.variant))?;
+        Ok((v, self.de))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for ValueDeserializer<'de> {
+    type Error = SerdeError;
+
+    fn unit_variant(self) -> Result<(), SerdeError> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, SerdeError> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, SerdeError> {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        de::Deserializer::deserialize_map(self, visitor)
+    }
+}
+
+/// Deserializer for one dotted key component used as a map key, the inverse of the key serializer.
+struct KeyDeserializer<'de>(&'de str);
+
+macro_rules! deserialize_key_number {
+    ($($method:ident => $visit:ident),*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+                visitor.$visit(self.0.parse().map_err(|_| SerdeError(format!("invalid numeric key '{}'", self.0)))?)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for KeyDeserializer<'de> {
+    type Error = SerdeError;
+
+    deserialize_key_number!(
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_char => visit_char
+    );
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_borrowed_str(self.0)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        visitor.visit_enum(BorrowedStrDeserializer::new(self.0))
+    }
+
+    serde::forward_to_deserialize_any! {
+        i128 u128 f32 f64 str string bytes byte_buf unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::dictionary::to_bytes;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Limits {
+        limit: u32,
+        rate: f64,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    enum Mode {
+        Off,
+        Fixed(u16),
+        Range { low: i8, high: i8 },
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Raw<'a> {
+        #[serde(borrow, with = "serde_bytes_as_raw")]
+        secret: &'a [u8],
+        name: &'a str,
+    }
+
+    mod serde_bytes_as_raw {
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(v: &[u8], s: S) -> Result<S::Ok, S::Error> {
+            s.serialize_bytes(v)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<&'de [u8], D::Error> {
+            <&[u8]>::deserialize(d)
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let modes = vec![Mode::Off, Mode::Fixed(7), Mode::Range { low: -1, high: 1 }];
+        assert_eq!(from_bytes::<Vec<Mode>>(&to_bytes(&modes).unwrap()).unwrap(), modes);
+
+        let tags: HashMap<u32, Option<String>> = [(1, Some(String::new())), (1000, Some(String::from("a=b\n")))]
+            .into_iter()
+            .collect();
+        assert_eq!(
+            from_bytes::<HashMap<u32, Option<String>>>(&to_bytes(&tags).unwrap()).unwrap(),
+            tags
+        );
+
+        let nested = vec![(0x8056c2e21c000001_u64, -300_i64, true), (0, i64::MIN, false)];
+        assert_eq!(
+            from_bytes::<Vec<(u64, i64, bool)>>(&to_bytes(&nested).unwrap()).unwrap(),
+            nested
+        );
+
+        let empty: HashMap<String, Vec<u32>> = [(String::from("e"), Vec::new())].into_iter().collect();
+        assert_eq!(
+            from_bytes::<HashMap<String, Vec<u32>>>(&to_bytes(&empty).unwrap()).unwrap(),
+            empty
+        );
+
+        let raw = Raw { secret: &[0, b'\n', b'=', 0xff], name: "borrowed" };
+        let d = Dictionary::from_bytes(&to_bytes(&raw).unwrap()).unwrap();
+        assert_eq!(d.get_bytes("secret"), Some(raw.secret));
+        assert_eq!(from_dictionary::<Raw>(&d).unwrap(), raw);
+    }
+
+    #[test]
+    fn sequences_with_none() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Element {
+            a: Option<u32>,
+            b: Option<String>,
+        }
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct S {
+            v: Vec<Option<u32>>,
+            e: Vec<Element>,
+            t: (Option<u8>, Option<u8>),
+        }
+
+        for v in [
+            vec![Some(1), None, Some(3)],
+            vec![Some(1), None],
+            vec![None, Some(0)],
+            vec![None, None],
+            vec![None],
+            Vec::new(),
+        ] {
+            let s = S {
+                v,
+                e: vec![
+                    Element { a: None, b: Some(String::from("x")) },
+                    Element { a: None, b: None },
+                    Element { a: Some(2), b: None },
+                ],
+                t: (Some(1), None),
+            };
+            assert_eq!(from_bytes::<S>(&to_bytes(&s).unwrap()).unwrap(), s);
+        }
+        let m: HashMap<&str, Vec<Option<u32>>> = [("v", vec![Some(1), None, Some(3), None])].into_iter().collect();
+        assert_eq!(to_bytes(&m).unwrap(), b"v=4\nv.0=1\nv.2=3\n");
+
+        assert!(to_bytes(&vec![Some(1), None]).is_err());
+        let mut d = Dictionary::new();
+        d.set_u64("v.0", 1).unwrap();
+        d.set_u64("v.2", 3).unwrap();
+        assert!(from_dictionary::<HashMap<String, Vec<u32>>>(&d).is_err());
+        d.set_u64("v.70000", 1).unwrap();
+        assert!(from_dictionary::<HashMap<String, Vec<Option<u32>>>>(&d).is_err());
+    }
+
+    #[test]
+    fn deserialize_errors() {
+        let mut d = Dictionary::new();
+        d.set_str("limit", "zz").unwrap();
+        d.set_str("rate", "1").unwrap();
+        assert!(from_dictionary::<Limits>(&d).is_err());
+        d.set_u64("limit", 0x1_0000_0000).unwrap();
+        assert!(from_dictionary::<Limits>(&d).is_err());
+        d.set_u64("limit", 1).unwrap();
+        assert_eq!(from_dictionary::<Limits>(&d).unwrap(), Limits { limit: 1, rate: 1.0 });
+        let _ = d.remove("rate");
+        assert!(from_dictionary::<Limits>(&d).is_err());
+    }
+}
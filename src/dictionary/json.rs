@@ -0,0 +1,169 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ *
+ * (c) ZeroTier, Inc.
+ * https://www.zerotier.com/
+ */
+
+use std::collections::BTreeMap;
+
+use serde::de::{MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::Dictionary;
+use crate::base64;
+
+/// Key of the object that holds a base64 encoded binary value in human readable formats.
+pub const BASE64_TAG: &str = "base64";
+
+/// A value that serializes as a string if it's valid UTF-8 and as a tagged base64 object otherwise.
+struct HumanValue<'a>(&'a [u8]);
+
+impl<'a> Serialize for HumanValue<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if let Ok(s) = std::str::from_utf8(self.0) {
+            serializer.serialize_str(s)
+        } else {
+            let mut m = serializer.serialize_map(Some(1))?;
+            m.serialize_entry(BASE64_TAG, base64::to_string(self.0).as_str())?;
+            m.end()
+        }
+    }
+}
+
+/// Owned string for deserializing keys, since serde is built without its String support.
+struct OwnedStr(String);
+
+impl<'de> Deserialize<'de> for OwnedStr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct OwnedStrVisitor;
+
+        impl<'de> Visitor<'de> for OwnedStrVisitor {
+            type Value = OwnedStr;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("string")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(OwnedStr(String::from(v)))
+            }
+        }
+
+        deserializer.deserialize_str(OwnedStrVisitor)
+    }
+}
+
+struct HumanValueVisitor;
+
+impl<'de> Visitor<'de> for HumanValueVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("string or base64 tagged object")
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(v.as_bytes().to_vec())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut value = None;
+        while let Some((k, v)) = map.next_entry::<OwnedStr, OwnedStr>()? {
+            if k.0 != BASE64_TAG || value.is_some() {
+                return Err(serde::de::Error::custom("expected a single base64 entry"));
+            }
+            value =
+                Some(base64::from_string(v.0.as_bytes()).ok_or_else(|| serde::de::Error::custom("invalid base64"))?);
+        }
+        value.ok_or_else(|| serde::de::Error::custom("expected a single base64 entry"))
+    }
+}
+
+struct HumanValueOwned(Vec<u8>);
+
+impl<'de> Deserialize<'de> for HumanValueOwned {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(HumanValueVisitor).map(Self)
+    }
+}
+
+struct DictionaryVisitor;
+
+impl<'de> Visitor<'de> for DictionaryVisitor {
+    type Value = Dictionary;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("Dictionary")
+    }
+
+    fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        Dictionary::from_bytes(v).map_err(|e| E::custom(e.to_string()))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut entries = BTreeMap::new();
+        while let Some((k, v)) = map.next_entry::<OwnedStr, HumanValueOwned>()? {
+            if entries.insert(k.0, v.0).is_some() {
+                return Err(serde::de::Error::custom("duplicate key"));
+            }
+        }
+        Ok(Dictionary::from_entries(entries))
+    }
+}
+
+impl Serialize for Dictionary {
+    /// Human readable formats like JSON get an object with string values, or {"base64": "..."} for
+    /// values that are not valid UTF-8. Other formats get the binary encoding as a byte array.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            let mut m = serializer.serialize_map(Some(self.entries.len()))?;
+            for (k, v) in self.entries.iter() {
+                m.serialize_entry(k.as_str(), &HumanValue(v))?;
+            }
+            m.end()
+        } else {
+            serializer.serialize_bytes(self.to_binary_bytes().as_slice())
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Dictionary {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_map(DictionaryVisitor)
+        } else {
+            deserializer.deserialize_bytes(DictionaryVisitor)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json() {
+        let mut d = Dictionary::new();
+        d.set_str("name", "node \"1\"\n").unwrap();
+        d.set_u64("id", 0x1234).unwrap();
+        d.set_bytes("secret", vec![0xff, 0, 1]).unwrap();
+        d.set_bytes("empty", Vec::new()).unwrap();
+
+        let j = serde_json::to_string(&d).unwrap();
+        assert_eq!(
+            j,
+            r#"{"empty":"","id":"1234","name":"node \"1\"\n","secret":{"base64":"_wAB"}}"#
+        );
+        assert_eq!(serde_json::from_str::<Dictionary>(&j).unwrap(), d);
+        let v: serde_json::Value = serde_json::to_value(&d).unwrap();
+        assert_eq!(serde_json::from_value::<Dictionary>(v).unwrap(), d);
+
+        assert!(serde_json::from_str::<Dictionary>(r#"{"a":{"hex":"00"}}"#).is_err());
+        assert!(serde_json::from_str::<Dictionary>(r#"{"a":{"base64":"!"}}"#).is_err());
+        assert!(serde_json::from_str::<Dictionary>(r#"{"a":1}"#).is_err());
+        assert!(serde_json::from_str::<Dictionary>(r#"{"a":"1","a":"2"}"#).is_err());
+    }
+}
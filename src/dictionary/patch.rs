@@ -0,0 +1,223 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ *
+ * (c) ZeroTier, Inc.
+ * https://www.zerotier.com/
+ */
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::Dictionary;
+use crate::error::{DictionaryLimitError, DictionaryMergeError, InvalidParameterError};
+
+const ADDED_PREFIX: char = '+';
+const CHANGED_PREFIX: char = '~';
+const REMOVED_PREFIX: char = '-';
+
+/// A set of changes that turns one Dictionary into another, see diff().
+///
+/// A patch serializes as a Dictionary whose keys are prefixed by '+' if added, '~' if changed, or
+/// '-' if removed. Removed keys have empty values.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DictionaryPatch {
+    pub added: BTreeMap<String, Vec<u8>>,
+    pub changed: BTreeMap<String, Vec<u8>>,
+    pub removed: BTreeSet<String>,
+}
+
+/// How Dictionary::merge() resolves keys present in both dictionaries with different values.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MergePolicy {
+    /// Keep the value already in the dictionary being merged into.
+    KeepExisting,
+    /// Replace it with the value from the other dictionary.
+    Overwrite,
+    /// Fail and leave the dictionary unmodified.
+    Fail,
+}
+
+/// Compute a patch that turns 'a' into 'b' when applied to it.
+pub fn diff(a: &Dictionary, b: &Dictionary) -> DictionaryPatch {
+    let mut patch = DictionaryPatch::default();
+    for (k, v) in b.entries.iter() {
+        match a.entries.get(k) {
+            None => {
+                let _ = patch.added.insert(k.clone(), v.clone());
+            }
+            Some(old) => {
+                if old != v {
+                    let _ = patch.changed.insert(k.clone(), v.clone());
+                }
+            }
+        }
+    }
+    for k in a.entries.keys() {
+        if !b.entries.contains_key(k) {
+            let _ = patch.removed.insert(k.clone());
+        }
+    }
+    patch
+}
+
+impl DictionaryPatch {
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+
+    pub fn to_dictionary(&self) -> Dictionary {
+        let mut entries = BTreeMap::new();
+        let prefixed = |p: char, k: &str| {
+            let mut pk = String::with_capacity(k.len() + 1);
+            pk.push(p);
+            pk.push_str(k);
+            pk
+        };
+        for (k, v) in self.added.iter() {
+            let _ = entries.insert(prefixed(ADDED_PREFIX, k), v.clone());
+        }
+        for (k, v) in self.changed.iter() {
+            let _ = entries.insert(prefixed(CHANGED_PREFIX, k), v.clone());
+        }
+        for k in self.removed.iter() {
+            let _ = entries.insert(prefixed(REMOVED_PREFIX, k), Vec::new());
+        }
+        Dictionary::from_entries(entries)
+    }
+
+    /// Serialize this patch in the Dictionary wire format.
+    #[inline(always)]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_dictionary().to_bytes()
+    }
+
+    /// Decode a patch from a dictionary created by to_dictionary().
+    pub fn from_dictionary(d: &Dictionary) -> Result<Self, InvalidParameterError> {
+        let mut patch = Self::default();
+        for (pk, v) in d.entries.iter() {
+            let mut chars = pk.chars();
+            let op = chars.next();
+            let k = String::from(chars.as_str());
+            if k.is_empty() {
+                return Err(InvalidParameterError("empty key in dictionary patch"));
+            }
+            match op {
+                Some(ADDED_PREFIX) => {
+                    let _ = patch.added.insert(k, v.clone());
+                }
+                Some(CHANGED_PREFIX) => {
+                    let _ = patch.changed.insert(k, v.clone());
+                }
+                Some(REMOVED_PREFIX) => {
+                    let _ = patch.removed.insert(k);
+                }
+                _ => {
+                    return Err(InvalidParameterError("invalid dictionary patch operation"));
+                }
+            }
+        }
+        Ok(patch)
+    }
+}
+
+impl Dictionary {
+    /// Apply a patch created by diff(), removing keys before adding or changing them.
+    ///
+    /// Keys are set regardless of whether they were previously present. If the result would exceed
+    /// this dictionary's limits it is left unmodified.
+    pub fn apply(&mut self, patch: &DictionaryPatch) -> Result<(), DictionaryLimitError> {
+        let mut d = self.clone();
+        for k in patch.removed.iter() {
+            let _ = d.remove(k);
+        }
+        for (k, v) in patch.added.iter().chain(patch.changed.iter()) {
+            d.set_bytes(k, v.clone())?;
+        }
+        *self = d;
+        Ok(())
+    }
+
+    /// Merge all entries from another dictionary into this one, resolving conflicts with a policy.
+    ///
+    /// On error this dictionary is left unmodified.
+    pub fn merge(&mut self, other: &Dictionary, policy: MergePolicy) -> Result<(), DictionaryMergeError> {
+        let mut d = self.clone();
+        for (k, v) in other.entries.iter() {
+            match d.entries.get(k) {
+                Some(existing) if existing == v => {}
+                Some(_) => match policy {
+                    MergePolicy::KeepExisting => {}
+                    MergePolicy::Overwrite => {
+                        d.set_bytes(k, v.clone())?;
+                    }
+                    MergePolicy::Fail => {
+                        return Err(DictionaryMergeError::Conflict(k.clone()));
+                    }
+                },
+                None => {
+                    d.set_bytes(k, v.clone())?;
+                }
+            }
+        }
+        *self = d;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dictionary::DictionaryLimits;
+    use crate::error::DictionaryLimit;
+
+    #[test]
+    fn patch_and_merge() {
+        let mut a = Dictionary::new();
+        a.set_str("name", "old").unwrap();
+        a.set_u64("id", 1).unwrap();
+        a.set_str("gone", "x").unwrap();
+        let mut b = Dictionary::new();
+        b.set_str("name", "new\n=").unwrap();
+        b.set_u64("id", 1).unwrap();
+        b.set_str("net.added", "y").unwrap();
+
+        let patch = diff(&a, &b);
+        assert_eq!(patch.added.keys().collect::<Vec<_>>(), ["net.added"]);
+        assert_eq!(patch.changed.keys().collect::<Vec<_>>(), ["name"]);
+        assert_eq!(patch.removed.iter().collect::<Vec<_>>(), ["gone"]);
+        assert!(diff(&a, &a).is_empty());
+
+        let wire = patch.to_bytes();
+        assert_eq!(wire, b"+net.added=y\n-gone=\n~name=new\\n\\e\n");
+        let patch2 = DictionaryPatch::from_dictionary(&Dictionary::from_bytes(&wire).unwrap()).unwrap();
+        assert_eq!(patch2, patch);
+        let mut a2 = a.clone();
+        a2.apply(&patch2).unwrap();
+        assert_eq!(a2, b);
+        assert!(DictionaryPatch::from_dictionary(&Dictionary::from_bytes(b"*x=1\n").unwrap()).is_err());
+        assert!(DictionaryPatch::from_dictionary(&Dictionary::from_bytes(b"+=1\n").unwrap()).is_err());
+
+        let mut limited = Dictionary::with_limits(DictionaryLimits { max_entries: 2, ..DictionaryLimits::UNLIMITED });
+        limited.set_str("name", "old").unwrap();
+        assert_eq!(
+            limited.apply(&diff(&Dictionary::new(), &b)),
+            Err(DictionaryLimitError(DictionaryLimit::Entries))
+        );
+        assert_eq!(limited.len(), 1);
+
+        let mut m = a.clone();
+        assert_eq!(
+            m.merge(&b, MergePolicy::Fail),
+            Err(DictionaryMergeError::Conflict(String::from("name")))
+        );
+        assert_eq!(m, a);
+        m.merge(&b, MergePolicy::KeepExisting).unwrap();
+        assert_eq!(m.get_str("name"), Some("old"));
+        assert_eq!(m.get_str("net.added"), Some("y"));
+        assert_eq!(m.len(), 4);
+        m.merge(&b, MergePolicy::Overwrite).unwrap();
+        assert_eq!(m.get_str("name"), Some("new\n="));
+        assert_eq!(m.get_str("gone"), Some("x"));
+    }
+}
@@ -0,0 +1,127 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ *
+ * (c) ZeroTier, Inc.
+ * https://www.zerotier.com/
+ */
+
+use super::{Dictionary, DictionaryLimits};
+
+/// A read-only view of the keys in a Dictionary that start with a prefix, see Dictionary::scope().
+///
+/// Keys passed to and returned by a scope have the prefix removed.
+#[derive(Clone)]
+pub struct DictionaryScope<'a> {
+    dict: &'a Dictionary,
+    prefix: String,
+}
+
+impl<'a> DictionaryScope<'a> {
+    #[inline(always)]
+    pub fn prefix(&self) -> &str {
+        self.prefix.as_str()
+    }
+
+    #[inline(always)]
+    pub fn limits(&self) -> &DictionaryLimits {
+        self.dict.limits()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    pub fn get_bytes(&self, k: &str) -> Option<&'a [u8]> {
+        let mut pk = String::with_capacity(self.prefix.len() + k.len());
+        pk.push_str(self.prefix.as_str());
+        pk.push_str(k);
+        self.dict.get_bytes(pk.as_str())
+    }
+
+    super::typed_getters!();
+
+    /// Get a narrower view within this one, e.g. scope("mcast.") within a scope for "net.".
+    pub fn scope(&self, prefix: &str) -> DictionaryScope<'a> {
+        let mut p = self.prefix.clone();
+        p.push_str(prefix);
+        DictionaryScope { dict: self.dict, prefix: p }
+    }
+
+    /// Iterate over entries in this scope in sorted key order with the prefix removed from keys.
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, &'a [u8])> + '_ {
+        self.dict
+            .iter_prefix(self.prefix.as_str())
+            .map(|(k, v)| (&k[self.prefix.len()..], v.as_slice()))
+    }
+}
+
+impl Dictionary {
+    /// Iterate over entries whose keys start with a prefix in sorted key order.
+    pub fn iter_prefix<'a, 'b>(&'a self, prefix: &'b str) -> impl Iterator<Item = (&'a String, &'a Vec<u8>)> + 'b
+    where
+        'a: 'b,
+    {
+        self.entries
+            .range::<str, _>((std::ops::Bound::Included(prefix), std::ops::Bound::Unbounded))
+            .take_while(move |(k, _)| k.starts_with(prefix))
+    }
+
+    /// Get a read-only view of the keys starting with a prefix such as "net.".
+    pub fn scope(&self, prefix: &str) -> DictionaryScope<'_> {
+        DictionaryScope { dict: self, prefix: String::from(prefix) }
+    }
+
+    /// Remove all entries whose keys start with a prefix, returning the number removed.
+    pub fn remove_prefix(&mut self, prefix: &str) -> usize {
+        let keys: Vec<String> = self.iter_prefix(prefix).map(|(k, _)| k.clone()).collect();
+        for k in keys.iter() {
+            let _ = self.remove(k);
+        }
+        keys.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefix_scopes() {
+        let mut d = Dictionary::new();
+        d.set_str("name", "n").unwrap();
+        d.set_u64("net.mtu", 2800).unwrap();
+        d.set_u64("net.mcast.limit", 32).unwrap();
+        d.set_bool("net.mcast.enabled", true).unwrap();
+        d.set_str("net", "not in scope").unwrap();
+        d.set_str("netmask", "not in scope").unwrap();
+        d.set_str("zone", "z").unwrap();
+
+        assert_eq!(
+            d.iter_prefix("net.").map(|(k, _)| k.as_str()).collect::<Vec<_>>(),
+            ["net.mcast.enabled", "net.mcast.limit", "net.mtu"]
+        );
+        assert_eq!(d.iter_prefix("x").count(), 0);
+        assert_eq!(d.iter_prefix("").count(), d.len());
+
+        let net = d.scope("net.");
+        assert_eq!(net.get_u64("mtu"), Some(2800));
+        assert!(net.get_str("name").is_none());
+        let mcast = net.scope("mcast.");
+        assert_eq!(mcast.prefix(), "net.mcast.");
+        assert_eq!(mcast.get_u64("limit"), Some(32));
+        assert_eq!(mcast.get_bool("enabled"), Some(true));
+        assert_eq!(
+            mcast.iter().collect::<Vec<_>>(),
+            [("enabled", &b"1"[..]), ("limit", &b"20"[..])]
+        );
+        assert!(d.scope("none.").is_empty());
+
+        assert_eq!(d.remove_prefix("net."), 3);
+        assert_eq!(d.remove_prefix("net."), 0);
+        assert_eq!(d.len(), 4);
+        assert_eq!(d.size, d.to_bytes().len());
+        assert_eq!(d.get_str("netmask"), Some("not in scope"));
+    }
+}
@@ -0,0 +1,616 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ *
+ * (c) ZeroTier, Inc.
+ * https://www.zerotier.com/
+ */
+
+use std::collections::BTreeMap;
+
+use serde::ser::{self, Serialize};
+
+use super::{i64_value, u64_value, Dictionary, SerdeError};
+
+/// Serialize a value into a new Dictionary.
+///
+/// The value must be a struct, map or sequence. Nested fields flatten to dotted keys, integers are
+/// written in hex as with set_u64(), bools as '1' or '0', and byte arrays are stored as raw values.
+/// None values are omitted, and empty structs, maps and sequences are written as an empty value so
+/// they can be told apart from missing ones. A sequence that ends with None also gets its length as
+/// its own value, since the trailing elements would otherwise leave no trace.
+pub fn to_dictionary<T: Serialize + ?Sized>(value: &T) -> Result<Dictionary, SerdeError> {
+    let mut entries = BTreeMap::new();
+    value.serialize(ValueSerializer { dict: &mut entries, key: String::new() })?;
+    Ok(Dictionary::from_entries(entries))
+}
+
+/// Serialize a value into the Dictionary wire format, see to_dictionary().
+pub fn to_bytes<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, SerdeError> {
+    to_dictionary(value).map(|d| d.to_bytes())
+}
+
+fn child_key(key: &str, name: &str) -> Result<String, SerdeError> {
+    if name.is_empty() || name.contains('.') {
+        return Err(SerdeError(format!("invalid key component '{}'", name)));
+    }
+    if key.is_empty() {
+        Ok(String::from(name))
+    } else {
+        let mut k = String::with_capacity(key.len() + 1 + name.len());
+        k.push_str(key);
+        k.push('.');
+        k.push_str(name);
+        Ok(k)
+    }
+}
+
+pub(super) struct ValueSerializer<'a> {
+    pub(super) dict: &'a mut BTreeMap<String, Vec<u8>>,
+    pub(super) key: String,
+}
+
+impl<'a> ValueSerializer<'a> {
+    fn put(self, v: Vec<u8>) -> Result<(), SerdeError> {
+        if self.key.is_empty() {
+            return Err(SerdeError(String::from(
+                "top level value must be a struct, map or sequence",
+            )));
+        }
+        let _ = self.dict.insert(self.key, v);
+        Ok(())
+    }
+
+    fn compound(self) -> Compound<'a> {
+        let start_len = self.dict.len();
+        Compound {
+            dict: self.dict,
+            key: self.key,
+            index: 0,
+            pending_key: None,
+            start_len,
+            trailing_none: false,
+        }
+    }
+}
+
+impl<'a> ser::Serializer for ValueSerializer<'a> {
+    type Ok = ();
+    type Error = SerdeError;
+    type SerializeSeq = Compound<'a>;
+    type SerializeTuple = Compound<'a>;
+    type SerializeTupleStruct = Compound<'a>;
+    type SerializeTupleVariant = Compound<'a>;
+    type SerializeMap = Compound<'a>;
+    type SerializeStruct = Compound<'a>;
+    type SerializeStructVariant = Compound<'a>;
+
+    fn serialize_bool(self, v: bool) -> Result<(), SerdeError> {
+        self.put(vec![if v {
+            b'1'
+        } else {
+            b'0'
+        }])
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), SerdeError> {
+        self.put(i64_value(v as i64))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), SerdeError> {
+        self.put(i64_value(v as i64))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), SerdeError> {
+        self.put(i64_value(v as i64))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), SerdeError> {
+        self.put(i64_value(v))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<(), SerdeError> {
+        let mut s = format!("{:x}", v.unsigned_abs());
+        if v < 0 {
+            s.insert(0, '-');
+        }
+        self.put(s.into_bytes())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), SerdeError> {
+        self.put(u64_value(v as u64))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), SerdeError> {
+        self.put(u64_value(v as u64))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), SerdeError> {
+        self.put(u64_value(v as u64))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), SerdeError> {
+        self.put(u64_value(v))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<(), SerdeError> {
+        self.put(format!("{:x}", v).into_bytes())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), SerdeError> {
+        self.put(v.to_string().into_bytes())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), SerdeError> {
+        self.put(v.to_string().into_bytes())
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), SerdeError> {
+        self.put(v.to_string().into_bytes())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), SerdeError> {
+        self.put(v.as_bytes().to_vec())
+    }
+
+    fn collect_str<T: std::fmt::Display + ?Sized>(self, v: &T) -> Result<(), SerdeError> {
+        self.put(v.to_string().into_bytes())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), SerdeError> {
+        self.put(v.to_vec())
+    }
+
+    fn serialize_none(self) -> Result<(), SerdeError> {
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), SerdeError> {
+        self.put(Vec::new())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), SerdeError> {
+        self.put(Vec::new())
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<(), SerdeError> {
+        self.put(variant.as_bytes().to_vec())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<(), SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<(), SerdeError> {
+        let key = child_key(&self.key, variant)?;
+        value.serialize(ValueSerializer { dict: self.dict, key })
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Compound<'a>, SerdeError> {
+        Ok(self.compound())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Compound<'a>, SerdeError> {
+        Ok(self.compound())
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Compound<'a>, SerdeError> {
+        Ok(self.compound())
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Compound<'a>, SerdeError> {
+        let key = child_key(&self.key, variant)?;
+        Ok(ValueSerializer { dict: self.dict, key }.compound())
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Compound<'a>, SerdeError> {
+        Ok(self.compound())
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Compound<'a>, SerdeError> {
+        Ok(self.compound())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Compound<'a>, SerdeError> {
+        let key = child_key(&self.key, variant)?;
+        Ok(ValueSerializer { dict: self.dict, key }.compound())
+    }
+}
+
+pub(super) struct Compound<'a> {
+    dict: &'a mut BTreeMap<String, Vec<u8>>,
+    key: String,
+    index: usize,
+    pending_key: Option<String>,
+    start_len: usize,
+    trailing_none: bool,
+}
+
+impl<'a> Compound<'a> {
+    fn element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        let key = child_key(&self.key, self.index.to_string().as_str())?;
+        self.index += 1;
+        let len = self.dict.len();
+        value.serialize(ValueSerializer { dict: self.dict, key })?;
+        self.trailing_none = self.dict.len() == len;
+        Ok(())
+    }
+
+    fn field<T: Serialize + ?Sized>(&mut self, name: &str, value: &T) -> Result<(), SerdeError> {
+        let key = child_key(&self.key, name)?;
+        value.serialize(ValueSerializer { dict: self.dict, key })
+    }
+
+    fn finish(self) -> Result<(), SerdeError> {
+        if self.trailing_none {
+            if self.key.is_empty() {
+                return Err(SerdeError(String::from("top level sequence can't end with None")));
+            }
+            let _ = self.dict.insert(self.key, u64_value(self.index as u64));
+        } else if self.dict.len() == self.start_len && !self.key.is_empty() {
+            // Mark empty containers so they deserialize as present but empty rather than missing.
+            let _ = self.dict.insert(self.key, Vec::new());
+        }
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeSeq for Compound<'a> {
+    type Ok = ();
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), SerdeError> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeTuple for Compound<'a> {
+    type Ok = ();
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), SerdeError> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeTupleStruct for Compound<'a> {
+    type Ok = ();
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), SerdeError> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeTupleVariant for Compound<'a> {
+    type Ok = ();
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), SerdeError> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeMap for Compound<'a> {
+    type Ok = ();
+    type Error = SerdeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), SerdeError> {
+        self.pending_key = Some(key.serialize(KeySerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        let name = self
+            .pending_key
+            .take()
+            .ok_or_else(|| SerdeError(String::from("map value without key")))?;
+        self.field(name.as_str(), value)
+    }
+
+    fn end(self) -> Result<(), SerdeError> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeStruct for Compound<'a> {
+    type Ok = ();
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, name: &'static str, value: &T) -> Result<(), SerdeError> {
+        self.field(name, value)
+    }
+
+    fn end(self) -> Result<(), SerdeError> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeStructVariant for Compound<'a> {
+    type Ok = ();
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, name: &'static str, value: &T) -> Result<(), SerdeError> {
+        self.field(name, value)
+    }
+
+    fn end(self) -> Result<(), SerdeError> {
+        self.finish()
+    }
+}
+
+/// Serializer for map keys, which become one component of a dotted key.
+/// Strings are used as-is and integers are written in decimal so sequences and integer keyed maps look alike.
+struct KeySerializer;
+
+fn key_error() -> SerdeError {
+    SerdeError(String::from("map keys must be strings, chars, bools or integers"))
+}
+
+impl ser::Serializer for KeySerializer {
+    type Ok = String;
+    type Error = SerdeError;
+    type SerializeSeq = ser::Impossible<String, SerdeError>;
+    type SerializeTuple = ser::Impossible<String, SerdeError>;
+    type SerializeTupleStruct = ser::Impossible<String, SerdeError>;
+    type SerializeTupleVariant = ser::Impossible<String, SerdeError>;
+    type SerializeMap = ser::Impossible<String, SerdeError>;
+    type SerializeStruct = ser::Impossible<String, SerdeError>;
+    type SerializeStructVariant = ser::Impossible<String, SerdeError>;
+
+    fn serialize_bool(self, v: bool) -> Result<String, SerdeError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<String, SerdeError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<String, SerdeError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<String, SerdeError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<String, SerdeError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<String, SerdeError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<String, SerdeError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<String, SerdeError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<String, SerdeError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<String, SerdeError> {
+        Err(key_error())
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<String, SerdeError> {
+        Err(key_error())
+    }
+
+    fn serialize_char(self, v: char) -> Result<String, SerdeError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<String, SerdeError> {
+        Ok(String::from(v))
+    }
+
+    fn collect_str<T: std::fmt::Display + ?Sized>(self, v: &T) -> Result<String, SerdeError> {
+        self.serialize_str(v.to_string().as_str())
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<String, SerdeError> {
+        Err(key_error())
+    }
+
+    fn serialize_none(self) -> Result<String, SerdeError> {
+        Err(key_error())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, _value: &T) -> Result<String, SerdeError> {
+        Err(key_error())
+    }
+
+    fn serialize_unit(self) -> Result<String, SerdeError> {
+        Err(key_error())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<String, SerdeError> {
+        Err(key_error())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<String, SerdeError> {
+        Ok(String::from(variant))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<String, SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<String, SerdeError> {
+        Err(key_error())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, SerdeError> {
+        Err(key_error())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, SerdeError> {
+        Err(key_error())
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, SerdeError> {
+        Err(key_error())
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, SerdeError> {
+        Err(key_error())
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, SerdeError> {
+        Err(key_error())
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct, SerdeError> {
+        Err(key_error())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, SerdeError> {
+        Err(key_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::Serialize;
+
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Mcast {
+        limit: u32,
+        enabled: bool,
+    }
+
+    #[derive(Serialize)]
+    enum Mode {
+        Off,
+        Fixed(u16),
+        Range { low: i8, high: i8 },
+    }
+
+    #[derive(Serialize)]
+    struct Network {
+        id: u64,
+        delta: i64,
+        mcast: Mcast,
+        routes: Vec<&'static str>,
+        empty: Vec<u32>,
+        tags: BTreeMap<u32, &'static str>,
+        missing: Option<u8>,
+        modes: Vec<Mode>,
+    }
+
+    #[test]
+    fn dotted_keys() {
+        let n = Network {
+            id: 0x8056c2e21c000001,
+            delta: -300,
+            mcast: Mcast { limit: 32, enabled: true },
+            routes: vec!["10.0.0.0/24", "10.0.1.0/24"],
+            empty: Vec::new(),
+            tags: [(1, "one"), (1000, "thousand")].into_iter().collect(),
+            missing: None,
+            modes: vec![Mode::Off, Mode::Fixed(7), Mode::Range { low: -1, high: 1 }],
+        };
+        assert_eq!(
+            String::from_utf8(to_bytes(&n).unwrap()).unwrap(),
+            "delta=-12c\n\
+             empty=\n\
+             id=8056c2e21c000001\n\
+             mcast.enabled=1\n\
+             mcast.limit=20\n\
+             modes.0=Off\n\
+             modes.1.Fixed=7\n\
+             modes.2.Range.high=1\n\
+             modes.2.Range.low=-1\n\
+             routes.0=10.0.0.0/24\n\
+             routes.1=10.0.1.0/24\n\
+             tags.1=one\n\
+             tags.1000=thousand\n"
+        );
+    }
+
+    #[test]
+    fn serialize_errors() {
+        assert!(to_bytes(&5_u32).is_err());
+        assert!(to_bytes(&[("a.b", 1)].into_iter().collect::<BTreeMap<_, _>>()).is_err());
+        assert!(to_bytes(&[("", 1)].into_iter().collect::<BTreeMap<_, _>>()).is_err());
+    }
+}
@@ -59,3 +59,252 @@ impl Debug for InvalidParameterError {
 }
 
 impl Error for InvalidParameterError {}
+
+/// A Dictionary limit, see dictionary::DictionaryLimits.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DictionaryLimit {
+    Entries,
+    KeyLength,
+    ValueLength,
+    TotalSize,
+    NestingDepth,
+}
+
+impl DictionaryLimit {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Entries => "too many entries",
+            Self::KeyLength => "key too long",
+            Self::ValueLength => "value too long",
+            Self::TotalSize => "total size too large",
+            Self::NestingDepth => "nesting too deep",
+        }
+    }
+}
+
+/// Error returned when setting a Dictionary value would exceed one of its limits.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct DictionaryLimitError(pub DictionaryLimit);
+
+impl Display for DictionaryLimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DictionaryLimitError: {}", self.0.as_str())
+    }
+}
+
+impl Debug for DictionaryLimitError {
+    #[inline(always)]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        <Self as Display>::fmt(self, f)
+    }
+}
+
+impl Error for DictionaryLimitError {}
+
+/// Error merging one Dictionary into another.
+#[derive(Clone, PartialEq, Eq)]
+pub enum DictionaryMergeError {
+    /// Both dictionaries have different values for this key and the merge policy is to fail.
+    Conflict(String),
+    /// The merged dictionary would exceed one of its limits.
+    Limit(DictionaryLimitError),
+}
+
+impl Display for DictionaryMergeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Conflict(k) => write!(f, "DictionaryMergeError: conflicting values for '{}'", k),
+            Self::Limit(e) => write!(f, "DictionaryMergeError: {}", e.0.as_str()),
+        }
+    }
+}
+
+impl Debug for DictionaryMergeError {
+    #[inline(always)]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        <Self as Display>::fmt(self, f)
+    }
+}
+
+impl Error for DictionaryMergeError {}
+
+impl From<DictionaryLimitError> for DictionaryMergeError {
+    #[inline(always)]
+    fn from(e: DictionaryLimitError) -> Self {
+        Self::Limit(e)
+    }
+}
+
+/// Reason a serialized Dictionary was rejected.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DictionaryParseErrorReason {
+    /// An unescaped '=' appeared in a value.
+    UnexpectedEquals,
+    /// A line ended before the '=' separating key from value.
+    MissingEquals,
+    /// A key is not valid UTF-8 after unescaping.
+    NonUtf8Key,
+    /// The same key appears more than once.
+    DuplicateKey,
+    /// The data ends with a '\' escape character.
+    TruncatedEscape,
+    /// The data does not end with a newline, so the last entry is incomplete.
+    TrailingData,
+    /// A binary format length is not a valid varint or runs past the end of the data.
+    InvalidLength,
+    /// Keys in binary format data are not in ascending order.
+    UnsortedKeys,
+    /// A key in binary format data is empty, which the text format can't represent.
+    EmptyKey,
+    /// The data exceeds one of the limits it was parsed with.
+    LimitExceeded(DictionaryLimit),
+}
+
+impl DictionaryParseErrorReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::UnexpectedEquals => "unexpected '=' in value",
+            Self::MissingEquals => "missing '=' after key",
+            Self::NonUtf8Key => "key is not valid UTF-8",
+            Self::DuplicateKey => "duplicate key",
+            Self::TruncatedEscape => "truncated escape sequence",
+            Self::TrailingData => "trailing data without newline",
+            Self::InvalidLength => "invalid or truncated length",
+            Self::UnsortedKeys => "keys not in sorted order",
+            Self::EmptyKey => "empty key",
+            Self::LimitExceeded(l) => l.as_str(),
+        }
+    }
+}
+
+/// Error parsing a serialized Dictionary, with the position of the problem.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct DictionaryParseError {
+    /// Byte offset into the input.
+    pub offset: usize,
+    /// Line number starting at 1.
+    pub line: usize,
+    pub reason: DictionaryParseErrorReason,
+}
+
+impl DictionaryParseError {
+    /// Create an error at a byte offset in 'input', computing the line number from it.
+    pub fn at(input: &[u8], offset: usize, reason: DictionaryParseErrorReason) -> Self {
+        let line = 1 + input[..offset.min(input.len())].iter().filter(|c| **c == b'\n').count();
+        Self { offset, line, reason }
+    }
+}
+
+impl Display for DictionaryParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "DictionaryParseError: {} at line {} (byte {})",
+            self.reason.as_str(),
+            self.line,
+            self.offset
+        )
+    }
+}
+
+impl Debug for DictionaryParseError {
+    #[inline(always)]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        <Self as Display>::fmt(self, f)
+    }
+}
+
+impl Error for DictionaryParseError {}
+
+impl From<DictionaryParseError> for std::io::Error {
+    fn from(e: DictionaryParseError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// Error decoding a hexadecimal string.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum HexDecodeError {
+    /// A character that is not a hex digit, or in lenient mode a separator, at a byte position.
+    InvalidCharacter { character: char, position: usize },
+    /// The string has an odd number of hex digits.
+    OddLength,
+    /// The string decodes to a different number of bytes than the destination holds.
+    InvalidLength { expected: usize, actual: usize },
+    /// The string has no hex digits but a number was expected.
+    Empty,
+    /// The value is too large for the integer type.
+    Overflow,
+}
+
+impl Display for HexDecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidCharacter { character, position } => {
+                write!(
+                    f,
+                    "HexDecodeError: invalid character {:?} at position {}",
+                    character, position
+                )
+            }
+            Self::OddLength => f.write_str("HexDecodeError: odd number of hex digits"),
+            Self::InvalidLength { expected, actual } => {
+                write!(f, "HexDecodeError: expected {} bytes, got {}", expected, actual)
+            }
+            Self::Empty => f.write_str("HexDecodeError: empty"),
+            Self::Overflow => f.write_str("HexDecodeError: value too large"),
+        }
+    }
+}
+
+impl Debug for HexDecodeError {
+    #[inline(always)]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        <Self as Display>::fmt(self, f)
+    }
+}
+
+impl Error for HexDecodeError {}
+
+/// Error strictly decoding a varint.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum VarintDecodeError {
+    /// The input ended before the final byte, so more data may complete it.
+    Incomplete,
+    /// The encoding is longer than necessary, i.e. it ends with a zero group.
+    NonCanonical,
+    /// The value does not fit in the integer type.
+    Overflow,
+}
+
+impl Display for VarintDecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Incomplete => "VarintDecodeError: incomplete",
+            Self::NonCanonical => "VarintDecodeError: non-canonical encoding",
+            Self::Overflow => "VarintDecodeError: value too large",
+        })
+    }
+}
+
+impl Debug for VarintDecodeError {
+    #[inline(always)]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        <Self as Display>::fmt(self, f)
+    }
+}
+
+impl Error for VarintDecodeError {}
+
+impl From<VarintDecodeError> for std::io::Error {
+    fn from(e: VarintDecodeError) -> Self {
+        std::io::Error::new(
+            if e == VarintDecodeError::Incomplete {
+                std::io::ErrorKind::UnexpectedEof
+            } else {
+                std::io::ErrorKind::InvalidData
+            },
+            e,
+        )
+    }
+}
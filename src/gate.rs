@@ -33,3 +33,189 @@ impl<const FREQ: i64> IntervalGate<FREQ> {
         }
     }
 }
+
+/// Randomization applied to delays produced by Backoff.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Jitter {
+    /// Delays follow the exponential curve exactly.
+    None,
+    /// Each delay is uniformly random between zero and the exponential curve.
+    Full,
+    /// Each delay is uniformly random between the initial delay and the previous delay times the multiplier.
+    Decorrelated,
+}
+
+/// Exponential backoff with optional jitter for retry and reconnect loops.
+///
+/// Delays and deadlines are in the same millisecond tick space as ms_monotonic(). Like IntervalGate
+/// this is not atomic, and gate() can be called on every pass through a service loop.
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    initial: i64,
+    multiplier: f64,
+    max: i64,
+    jitter: Jitter,
+    max_attempts: u32,
+    attempts: u32,
+    base: i64,
+    deadline: i64,
+    rng: u64,
+}
+
+impl Backoff {
+    /// Create a new backoff starting at 'initial' and growing by 'multiplier' up to 'max' milliseconds.
+    ///
+    /// A max_attempts of zero allows unlimited attempts.
+    pub fn new(initial: i64, multiplier: f64, max: i64, jitter: Jitter, max_attempts: u32) -> Self {
+        let initial = initial.max(1);
+        // Seed from the randomized hasher keys std uses for HashMap, which avoids needing a dependency.
+        let seed =
+            std::hash::BuildHasher::hash_one(&std::collections::hash_map::RandomState::new(), crate::ms_monotonic());
+        Self {
+            initial,
+            multiplier: multiplier.max(1.0),
+            max: max.max(initial),
+            jitter,
+            max_attempts,
+            attempts: 0,
+            base: initial,
+            deadline: crate::NEVER_HAPPENED_TICKS,
+            rng: seed | 1,
+        }
+    }
+
+    /// Get the number of delays produced since creation or the last reset().
+    #[inline(always)]
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Returns true if max_attempts is nonzero and has been reached.
+    #[inline(always)]
+    pub fn is_exhausted(&self) -> bool {
+        self.max_attempts != 0 && self.attempts >= self.max_attempts
+    }
+
+    /// Get the deadline set by the last call to gate() or next_deadline().
+    #[inline(always)]
+    pub fn deadline(&self) -> i64 {
+        self.deadline
+    }
+
+    /// Start over from the initial delay, e.g. after a successful connection.
+    pub fn reset(&mut self) {
+        self.attempts = 0;
+        self.base = self.initial;
+        self.deadline = crate::NEVER_HAPPENED_TICKS;
+    }
+
+    /// Get the next delay in milliseconds, or None if max_attempts has been reached.
+    pub fn next_delay(&mut self) -> Option<i64> {
+        if self.is_exhausted() {
+            return None;
+        }
+        let grown = ((self.base as f64) * self.multiplier).min(self.max as f64) as i64;
+        let delay = match self.jitter {
+            Jitter::None => self.base,
+            Jitter::Full => self.random_between(0, self.base),
+            Jitter::Decorrelated => {
+                if self.attempts == 0 {
+                    self.initial
+                } else {
+                    self.random_between(self.initial, grown)
+                }
+            }
+        };
+        self.base = match self.jitter {
+            Jitter::Decorrelated => delay,
+            _ => grown,
+        };
+        self.attempts += 1;
+        Some(delay)
+    }
+
+    /// Get the time at which the next attempt should be made given the current time.
+    ///
+    /// The result is also remembered and used by gate().
+    pub fn next_deadline(&mut self, time: i64) -> Option<i64> {
+        self.next_delay().map(|d| {
+            self.deadline = time.saturating_add(d);
+            self.deadline
+        })
+    }
+
+    /// Returns true if an attempt should be made now and schedules the one after it.
+    ///
+    /// The first call after creation or reset() returns true immediately. This returns false
+    /// forever once max_attempts is reached until reset() is called.
+    pub fn gate(&mut self, time: i64) -> bool {
+        time >= self.deadline && self.next_deadline(time).is_some()
+    }
+
+    fn random_between(&mut self, low: i64, high: i64) -> i64 {
+        // xorshift64* is plenty for spreading out retries and keeps this free of dependencies.
+        let mut x = self.rng;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.rng = x;
+        let r = x.wrapping_mul(0x2545f4914f6cdd1d);
+        if high > low {
+            low + (r % ((high - low) as u64 + 1)) as i64
+        } else {
+            low
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interval_gate() {
+        let mut g = IntervalGate::<1000>::default();
+        assert!(g.gate(0));
+        assert!(!g.gate(999));
+        assert!(g.gate(1000));
+    }
+
+    #[test]
+    fn backoff_without_jitter() {
+        let mut b = Backoff::new(100, 2.0, 1000, Jitter::None, 0);
+        let delays: Vec<i64> = (0..6).map(|_| b.next_delay().unwrap()).collect();
+        assert_eq!(delays, vec![100, 200, 400, 800, 1000, 1000]);
+        b.reset();
+        assert_eq!(b.next_delay(), Some(100));
+    }
+
+    #[test]
+    fn backoff_jitter_bounds() {
+        let mut full = Backoff::new(100, 2.0, 5000, Jitter::Full, 0);
+        let mut decorrelated = Backoff::new(100, 3.0, 5000, Jitter::Decorrelated, 0);
+        let mut cap = 100;
+        let mut prev = 100;
+        for _ in 0..1000 {
+            let d = full.next_delay().unwrap();
+            assert!((0..=cap).contains(&d));
+            cap = (cap * 2).min(5000);
+            let d = decorrelated.next_delay().unwrap();
+            assert!(d >= 100 && d <= (prev * 3).min(5000));
+            prev = d;
+        }
+    }
+
+    #[test]
+    fn backoff_gate() {
+        let mut b = Backoff::new(100, 2.0, 1000, Jitter::None, 3);
+        assert!(b.gate(0));
+        assert!(!b.gate(99));
+        assert!(b.gate(100));
+        assert_eq!(b.deadline(), 300);
+        assert!(b.gate(300));
+        assert!(b.is_exhausted());
+        assert!(!b.gate(10000));
+        b.reset();
+        assert!(b.gate(10000));
+    }
+}
@@ -6,23 +6,66 @@
  * https://www.zerotier.com/
  */
 
+mod dump;
+mod simd;
+
+pub use dump::{dump, parse_dump, HexDump, PARSE_DUMP_MAX_SIZE};
+
+use crate::error::{HexDecodeError, InvalidParameterError};
+
 pub const HEX_CHARS: [u8; 16] = [
     b'0', b'1', b'2', b'3', b'4', b'5', b'6', b'7', b'8', b'9', b'a', b'b', b'c', b'd', b'e', b'f',
 ];
 
+pub const HEX_CHARS_UPPER: [u8; 16] = [
+    b'0', b'1', b'2', b'3', b'4', b'5', b'6', b'7', b'8', b'9', b'A', b'B', b'C', b'D', b'E', b'F',
+];
+
+fn to_string_internal(b: &[u8], upper: bool) -> String {
+    let mut s = vec![0_u8; b.len() * 2];
+    simd::encode(b, s.as_mut_slice(), upper);
+    // The encoder only writes ASCII hex digits.
+    unsafe { String::from_utf8_unchecked(s) }
+}
+
 /// Encode a byte slice to a hexadecimal string.
+#[inline(always)]
 pub fn to_string(b: &[u8]) -> String {
-    let mut s = String::with_capacity(b.len() * 2);
-    s.reserve(b.len() * 2);
-    for c in b {
-        let x = *c as usize;
-        s.push(HEX_CHARS[x >> 4] as char);
-        s.push(HEX_CHARS[x & 0xf] as char);
+    to_string_internal(b, false)
+}
+
+/// Encode a byte slice to an uppercase hexadecimal string.
+#[inline(always)]
+pub fn to_string_upper(b: &[u8]) -> String {
+    to_string_internal(b, true)
+}
+
+fn encode_to_slice_internal(b: &[u8], out: &mut [u8], upper: bool) -> Result<usize, InvalidParameterError> {
+    let n = b.len() * 2;
+    if out.len() < n {
+        return Err(InvalidParameterError("output slice too small"));
     }
-    s
+    simd::encode(b, &mut out[..n], upper);
+    Ok(n)
+}
+
+/// Encode into the start of a slice without allocating, returning the number of characters written.
+///
+/// The slice must be at least twice as long as the input.
+#[inline(always)]
+pub fn encode_to_slice(b: &[u8], out: &mut [u8]) -> Result<usize, InvalidParameterError> {
+    encode_to_slice_internal(b, out, false)
+}
+
+/// Encode into the start of a slice in uppercase, see encode_to_slice().
+#[inline(always)]
+pub fn encode_upper_to_slice(b: &[u8], out: &mut [u8]) -> Result<usize, InvalidParameterError> {
+    encode_to_slice_internal(b, out, true)
 }
 
 /// Decode a hex string, ignoring all non-hexadecimal characters.
+///
+/// This silently drops a trailing odd digit. Use decode() or decode_lenient() to catch mistakes.
 pub fn from_string(s: &str) -> Vec<u8> {
     let mut b: Vec<u8> = Vec::with_capacity((s.len() / 2) + 1);
     let mut byte = 0_u8;
@@ -65,6 +108,7 @@ pub fn to_string_u64(mut i: u64, skip_leading_zeroes: bool) -> String {
     s
 }
 
+/// Decode a hex string as a u64, ignoring all non-hexadecimal characters. See decode_u64().
 pub fn from_string_u64(s: &str) -> u64 {
     let mut n = 0u64;
     let mut byte = 0_u8;
@@ -113,14 +157,133 @@ pub fn to_vec_u64(mut i: u64, skip_leading_zeroes: bool) -> Vec<u8> {
 /// Encode bytes from 'b' into hex characters in 'dest' and return the number of hex characters written.
 /// This will panic if the destination slice is smaller than twice the length of the source.
 pub fn to_hex_bytes(b: &[u8], dest: &mut [u8]) -> usize {
-    let mut j = 0;
-    for c in b {
-        let x = *c as usize;
-        dest[j] = HEX_CHARS[x >> 4];
-        dest[j + 1] = HEX_CHARS[x & 0xf];
-        j += 2;
+    let n = b.len() * 2;
+    simd::encode(b, &mut dest[..n], false);
+    n
+}
+
+#[inline(always)]
+fn nibble(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Check for separators skipped by the lenient decoders: ':', '-', and ASCII whitespace.
+#[inline(always)]
+pub fn is_separator(c: u8) -> bool {
+    c == b':' || c == b'-' || c.is_ascii_whitespace()
+}
+
+/// Call a function with each hex digit's value, returning the number of digits.
+fn for_each_nibble<F: FnMut(usize, u8)>(s: &str, lenient: bool, mut f: F) -> Result<usize, HexDecodeError> {
+    let mut n = 0;
+    for (position, c) in s.bytes().enumerate() {
+        if let Some(x) = nibble(c) {
+            f(n, x);
+            n += 1;
+        } else if !(lenient && is_separator(c)) {
+            return Err(HexDecodeError::InvalidCharacter {
+                character: s[position..].chars().next().unwrap_or(char::REPLACEMENT_CHARACTER),
+                position,
+            });
+        }
+    }
+    Ok(n)
+}
+
+fn decode_internal(s: &str, lenient: bool) -> Result<Vec<u8>, HexDecodeError> {
+    if !lenient && (s.len() & 1) == 0 {
+        let mut b = vec![0_u8; s.len() / 2];
+        if simd::decode(s.as_bytes(), b.as_mut_slice()) {
+            return Ok(b);
+        }
+    }
+    // Also used to find the position of the error if the fast path fails.
+    let mut b = vec![0_u8; s.len().div_ceil(2)];
+    let n = for_each_nibble(s, lenient, |i, x| b[i / 2] |= x << (((i & 1) ^ 1) * 4))?;
+    if (n & 1) != 0 {
+        return Err(HexDecodeError::OddLength);
+    }
+    b.truncate(n / 2);
+    Ok(b)
+}
+
+fn decode_to_slice_internal(s: &str, out: &mut [u8], lenient: bool) -> Result<(), HexDecodeError> {
+    if !lenient && s.len() == out.len() * 2 && simd::decode(s.as_bytes(), out) {
+        return Ok(());
     }
-    j
+    let expected = out.len();
+    out.fill(0);
+    let n = for_each_nibble(s, lenient, |i, x| {
+        if let Some(b) = out.get_mut(i / 2) {
+            *b |= x << (((i & 1) ^ 1) * 4);
+        }
+    })?;
+    if (n & 1) != 0 {
+        Err(HexDecodeError::OddLength)
+    } else if n / 2 != expected {
+        Err(HexDecodeError::InvalidLength { expected, actual: n / 2 })
+    } else {
+        Ok(())
+    }
+}
+
+fn decode_u64_internal(s: &str, lenient: bool) -> Result<u64, HexDecodeError> {
+    let mut v = 0_u64;
+    let mut overflow = false;
+    let n = for_each_nibble(s, lenient, |_, x| {
+        overflow |= (v >> 60) != 0;
+        v = (v << 4) | (x as u64);
+    })?;
+    if n == 0 {
+        Err(HexDecodeError::Empty)
+    } else if overflow {
+        Err(HexDecodeError::Overflow)
+    } else {
+        Ok(v)
+    }
+}
+
+/// Decode a hex string, failing on anything but an even number of hex digits.
+#[inline(always)]
+pub fn decode(s: &str) -> Result<Vec<u8>, HexDecodeError> {
+    decode_internal(s, false)
+}
+
+/// Decode a hex string, skipping separators such as ':', '-' and whitespace.
+#[inline(always)]
+pub fn decode_lenient(s: &str) -> Result<Vec<u8>, HexDecodeError> {
+    decode_internal(s, true)
+}
+
+/// Decode a hex string into a slice, failing unless it contains exactly enough digits to fill it.
+///
+/// The contents of the slice are unspecified if an error is returned.
+#[inline(always)]
+pub fn decode_to_slice(s: &str, out: &mut [u8]) -> Result<(), HexDecodeError> {
+    decode_to_slice_internal(s, out, false)
+}
+
+/// Decode a hex string into a slice, skipping separators, see decode_to_slice().
+#[inline(always)]
+pub fn decode_to_slice_lenient(s: &str, out: &mut [u8]) -> Result<(), HexDecodeError> {
+    decode_to_slice_internal(s, out, true)
+}
+
+/// Decode a hex number of any number of digits, failing if it doesn't fit in a u64.
+#[inline(always)]
+pub fn decode_u64(s: &str) -> Result<u64, HexDecodeError> {
+    decode_u64_internal(s, false)
+}
+
+/// Decode a hex number, skipping separators, see decode_u64().
+#[inline(always)]
+pub fn decode_u64_lenient(s: &str) -> Result<u64, HexDecodeError> {
+    decode_u64_internal(s, true)
 }
 
 #[cfg(test)]
@@ -191,4 +354,217 @@ mod tests {
         assert_eq!(num_hex_bytes, 6);
         assert_eq!(dest, [51, 52, 51, 48, 51, 48]);
     }
+
+    #[test]
+    fn strict_decode() {
+        assert_eq!(
+            decode("0123456789abcdefABCDEF").unwrap(),
+            [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef, 0xab, 0xcd, 0xef]
+        );
+        assert!(decode("").unwrap().is_empty());
+        assert_eq!(decode("abc"), Err(HexDecodeError::OddLength));
+        assert_eq!(
+            decode("ab:cd"),
+            Err(HexDecodeError::InvalidCharacter { character: ':', position: 2 })
+        );
+        assert_eq!(
+            decode("abcé"),
+            Err(HexDecodeError::InvalidCharacter { character: 'é', position: 3 })
+        );
+        assert_eq!(decode_lenient("ab:cd-ef 01\n").unwrap(), [0xab, 0xcd, 0xef, 0x01]);
+        assert_eq!(
+            decode_lenient("ab:cg"),
+            Err(HexDecodeError::InvalidCharacter { character: 'g', position: 4 })
+        );
+        assert_eq!(decode_lenient("ab:c"), Err(HexDecodeError::OddLength));
+
+        let mut out = [0_u8; 4];
+        decode_to_slice("deadBEEF", &mut out).unwrap();
+        assert_eq!(out, [0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(
+            decode_to_slice("dead", &mut out),
+            Err(HexDecodeError::InvalidLength { expected: 4, actual: 2 })
+        );
+        assert_eq!(
+            decode_to_slice("deadbeef00", &mut out),
+            Err(HexDecodeError::InvalidLength { expected: 4, actual: 5 })
+        );
+        decode_to_slice_lenient("01:02:03:04", &mut out).unwrap();
+        assert_eq!(out, [1, 2, 3, 4]);
+
+        assert_eq!(decode_u64("400"), Ok(0x400));
+        assert_eq!(decode_u64("0000ffffffffffffffff"), Ok(u64::MAX));
+        assert_eq!(decode_u64("1ffffffffffffffff"), Err(HexDecodeError::Overflow));
+        assert_eq!(decode_u64(""), Err(HexDecodeError::Empty));
+        assert_eq!(
+            decode_u64("0x400"),
+            Err(HexDecodeError::InvalidCharacter { character: 'x', position: 1 })
+        );
+        assert_eq!(decode_u64_lenient("8056c2e2-1c"), Ok(0x8056c2e21c));
+    }
+
+    fn reference_encode(b: &[u8], chars: &[u8; 16]) -> Vec<u8> {
+        b.iter()
+            .flat_map(|c| [chars[(*c >> 4) as usize], chars[(*c & 0xf) as usize]])
+            .collect()
+    }
+
+    #[test]
+    fn fast_paths_match_reference() {
+        let data: Vec<u8> = (0..300).map(|_| rand::random()).collect();
+        for len in 0..data.len() {
+            let b = &data[..len];
+            let lower = reference_encode(b, &HEX_CHARS);
+            let upper = reference_encode(b, &HEX_CHARS_UPPER);
+            assert_eq!(to_string(b).as_bytes(), lower.as_slice());
+            assert_eq!(to_string_upper(b).as_bytes(), upper.as_slice());
+
+            let mut out = vec![0_u8; len * 2];
+            simd::encode_swar(b, &mut out, false);
+            assert_eq!(out, lower);
+            simd::encode_swar(b, &mut out, true);
+            assert_eq!(out, upper);
+
+            let mut dec = vec![0_u8; len];
+            for hex in [&lower, &upper] {
+                assert!(simd::decode_swar(hex, &mut dec));
+                assert_eq!(dec, b);
+                assert_eq!(decode(std::str::from_utf8(hex).unwrap()).unwrap(), b);
+                decode_to_slice(std::str::from_utf8(hex).unwrap(), &mut dec).unwrap();
+                assert_eq!(dec, b);
+            }
+
+            #[cfg(target_arch = "x86_64")]
+            unsafe {
+                for upper_case in [false, true] {
+                    let expected = if upper_case {
+                        &upper
+                    } else {
+                        &lower
+                    };
+                    out.fill(0);
+                    let n = simd::x86::encode_sse2(b, &mut out, upper_case);
+                    assert_eq!(out[..n * 2], expected[..n * 2]);
+                    let n = simd::x86::decode_sse2(expected, &mut dec).unwrap();
+                    assert_eq!(dec[..n], b[..n]);
+                    if std::is_x86_feature_detected!("avx2") {
+                        out.fill(0);
+                        let n = simd::x86::encode_avx2(b, &mut out, upper_case);
+                        assert_eq!(out[..n * 2], expected[..n * 2]);
+                        let n = simd::x86::decode_avx2(expected, &mut dec).unwrap();
+                        assert_eq!(dec[..n], b[..n]);
+                    }
+                }
+            }
+        }
+
+        let mut out = [0_u8; 8];
+        assert_eq!(encode_to_slice(&[0xde, 0xad], &mut out).unwrap(), 4);
+        assert_eq!(&out[..4], b"dead");
+        assert_eq!(encode_upper_to_slice(&[0xbe, 0xef, 0x01, 0x23], &mut out).unwrap(), 8);
+        assert_eq!(&out, b"BEEF0123");
+        assert!(encode_to_slice(&[0; 5], &mut out).is_err());
+    }
+
+    #[test]
+    fn fast_paths_reject_invalid() {
+        let valid = "0123456789abcdefABCDEF".repeat(6);
+        for bad in ['g', 'G', '/', ':', '@', '`', ' ', '\u{0}', '\u{ff}'] {
+            for pos in 0..valid.len() {
+                let mut hex = valid.clone().into_bytes();
+                let mut c = [0_u8; 4];
+                let enc = bad.encode_utf8(&mut c).as_bytes();
+                if pos + enc.len() > hex.len() {
+                    continue;
+                }
+                hex[pos..pos + enc.len()].copy_from_slice(enc);
+                let s = std::str::from_utf8(&hex).unwrap();
+                assert_eq!(
+                    decode(s),
+                    Err(HexDecodeError::InvalidCharacter { character: bad, position: pos })
+                );
+                let mut out = vec![0_u8; hex.len() / 2];
+                assert!(!simd::decode_swar(&hex, &mut out));
+                assert!(!simd::decode(&hex, &mut out));
+            }
+        }
+    }
+
+    #[test]
+    fn hex_dump() {
+        let data = b"Hello, world!\n\x00\xffmore";
+        assert_eq!(
+            dump(data).to_string(),
+            "00000000  48 65 6c 6c 6f 2c 20 77 6f 72 6c 64 21 0a 00 ff  |Hello, world!...|\n\
+             00000010  6d 6f 72 65                                      |more|\n"
+        );
+        assert_eq!(
+            dump(&data[..6]).width(4).group(2).base_offset(0x100).to_string(),
+            "00000100  4865 6c6c  |Hell|\n00000104  6f2c       |o,|\n"
+        );
+        let mut s = String::from("packet:\n");
+        dump(b"ab").write_to(&mut s).unwrap();
+        assert_eq!(s, format!("packet:\n00000000  61 62{}  |ab|\n", " ".repeat(42)));
+        assert_eq!(dump(&[]).to_string(), "");
+
+        let data: Vec<u8> = (0..=255).collect();
+        for (width, group) in [(16, 1), (8, 2), (32, 4), (3, 1), (1, 1)] {
+            assert_eq!(
+                parse_dump(&dump(&data).width(width).group(group).to_string()).unwrap(),
+                data
+            );
+        }
+        let hexdump_c = "00000000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 ff  |Hello, world!...|\n\
+                         00000010  6d 6f 72 65                                       |more|\n\
+                         00000014\n";
+        assert_eq!(parse_dump(hexdump_c).unwrap(), b"Hello, world!\n\x00\xffmore");
+        assert_eq!(
+            parse_dump("00000000  41 42\n00000002  4g  |A|\n"),
+            Err(HexDecodeError::InvalidCharacter { character: 'g', position: 27 })
+        );
+
+        // Repeated rows are squeezed into a '*' line by both hexdump -C and xxd -a.
+        let mut squeezed = b"Hello, world!\n\x00\xff".to_vec();
+        squeezed.extend_from_slice(&[0; 32]);
+        squeezed.extend_from_slice(b"more");
+        let hexdump_c = "00000000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 ff  |Hello, world!...|\n\
+                         00000010  00 00 00 00 00 00 00 00  00 00 00 00 00 00 00 00  |................|\n\
+                         *\n\
+                         00000030  6d 6f 72 65                                       |more|\n\
+                         00000034\n";
+        assert_eq!(parse_dump(hexdump_c).unwrap(), squeezed);
+        let xxd = "00000000: 4865 6c6c 6f2c 2077 6f72 6c64 210a 00ff  Hello, world!...\n\
+                   00000010: 0000 0000 0000 0000 0000 0000 0000 0000  ................\n\
+                   *\n\
+                   00000030: 6d6f 7265                                more\n";
+        assert_eq!(parse_dump(xxd).unwrap(), squeezed);
+        let xxd_zeros = "00000000: 0000 0000 0000 0000 0000 0000 0000 0000  ................\n\
+                         *\n\
+                         00000030: 0000 0000 0000 0000 0000 0000 0000 0000  ................\n";
+        assert_eq!(parse_dump(xxd_zeros).unwrap(), [0; 64]);
+        assert_eq!(
+            parse_dump("00000100: 6162 2020 6364                           ab  cd\n").unwrap(),
+            b"ab  cd"
+        );
+        for (input, position) in [
+            ("*\n", 0),
+            ("00000000  41 42\n*\n", 16),
+            ("00000000  41 42\n *\n00000003\n", 17),
+            ("00000000  41 42\n*\n*\n00000008\n", 18),
+            // A squeeze can't claim more output than the cap, e.g. 64 GiB of "AB" from a few lines.
+            ("00000000  41 42\n*\n1000000000\n", 16),
+        ] {
+            assert_eq!(
+                parse_dump(input),
+                Err(HexDecodeError::InvalidCharacter { character: '*', position })
+            );
+        }
+        assert_eq!(
+            parse_dump(&format!("00000000  41 42\n*\n{:08x}\n", PARSE_DUMP_MAX_SIZE))
+                .unwrap()
+                .len(),
+            PARSE_DUMP_MAX_SIZE
+        );
+        assert_eq!(parse_dump("00000000  414\n"), Err(HexDecodeError::OddLength));
+    }
 }
@@ -0,0 +1,174 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ *
+ * (c) ZeroTier, Inc.
+ * https://www.zerotier.com/
+ */
+
+use std::fmt::{Display, Write};
+
+use super::{decode_lenient, HEX_CHARS};
+use crate::error::HexDecodeError;
+
+/// A hex dump with an offset column, grouped bytes, and an ASCII gutter, see dump().
+#[derive(Clone, Copy)]
+pub struct HexDump<'a> {
+    data: &'a [u8],
+    width: usize,
+    group: usize,
+    base_offset: usize,
+}
+
+/// Format bytes as a hex dump in the style of "hexdump -C".
+///
+/// Each line looks like this by default:
+///
+/// 00000000  48 65 6c 6c 6f 2c 20 77 6f 72 6c 64 21 0a 00 ff  |Hello, world!...|
+///
+/// The result implements Display, or it can be written to any fmt::Write with write_to().
+#[inline(always)]
+pub fn dump(data: &[u8]) -> HexDump<'_> {
+    HexDump { data, width: 16, group: 1, base_offset: 0 }
+}
+
+impl<'a> HexDump<'a> {
+    /// Set the number of bytes per line, which must be at least one.
+    #[inline(always)]
+    pub fn width(mut self, width: usize) -> Self {
+        self.width = width.max(1);
+        self
+    }
+
+    /// Set the number of bytes printed without spaces between them, e.g. 2 for "xxd" style groups.
+    #[inline(always)]
+    pub fn group(mut self, group: usize) -> Self {
+        self.group = group.max(1);
+        self
+    }
+
+    /// Set the offset printed for the first byte, e.g. to show the position within a larger buffer.
+    #[inline(always)]
+    pub fn base_offset(mut self, base_offset: usize) -> Self {
+        self.base_offset = base_offset;
+        self
+    }
+
+    pub fn write_to<W: Write>(&self, w: &mut W) -> std::fmt::Result {
+        for (i, line) in self.data.chunks(self.width).enumerate() {
+            write!(w, "{:08x} ", self.base_offset + i * self.width)?;
+            for j in 0..self.width {
+                if (j % self.group) == 0 {
+                    w.write_char(' ')?;
+                }
+                if let Some(b) = line.get(j) {
+                    w.write_char(HEX_CHARS[(*b >> 4) as usize] as char)?;
+                    w.write_char(HEX_CHARS[(*b & 0xf) as usize] as char)?;
+                } else {
+                    w.write_str("  ")?;
+                }
+            }
+            w.write_str("  |")?;
+            for b in line {
+                w.write_char(if b.is_ascii_graphic() || *b == b' ' {
+                    *b as char
+                } else {
+                    '.'
+                })?;
+            }
+            w.write_str("|\n")?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Display for HexDump<'a> {
+    #[inline(always)]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.write_to(f)
+    }
+}
+
+/// Maximum number of bytes parse_dump() will produce by expanding '*' lines.
+///
+/// A squeezed dump is only a few lines long whatever its offsets say, so without a cap a short input
+/// could claim a huge offset and make the parser allocate without bound.
+pub const PARSE_DUMP_MAX_SIZE: usize = 16777216;
+
+/// Parse a hex dump back into bytes.
+///
+/// This reads the output of dump() with any width or grouping as well as that of "hexdump -C" and
+/// "xxd". The offset column and the ASCII gutter are ignored, as are lines with only an offset. A
+/// '*' line, which both tools print in place of repeated rows, is expanded by repeating the row
+/// before it up to the offset of the next line, as long as that stays within PARSE_DUMP_MAX_SIZE. Error
+/// positions are byte offsets into the whole input.
+pub fn parse_dump(s: &str) -> Result<Vec<u8>, HexDecodeError> {
+    let mut data = Vec::new();
+    let mut first_offset = None;
+    let mut row = 0..0;
+    let mut squeeze = None;
+    let mut line_start = 0;
+    for line in s.split_inclusive('\n') {
+        let position = line_start;
+        line_start += line.len();
+
+        if line.trim() == "*" {
+            let star = position + line.find('*').unwrap_or(0);
+            if row.is_empty() || squeeze.is_some() {
+                return Err(HexDecodeError::InvalidCharacter { character: '*', position: star });
+            }
+            squeeze = Some(star);
+            continue;
+        }
+
+        let line = line.split('|').next().unwrap_or("").trim_end();
+        let offset_len = line.find(|c: char| !c.is_ascii_hexdigit()).unwrap_or(line.len());
+        let rest = &line[offset_len..];
+        let (hex_start, rest) = match rest.strip_prefix(':') {
+            // xxd separates its ASCII gutter with two spaces instead of a '|'.
+            Some(r) => (
+                position + offset_len + 1,
+                &r[..r.get(1..).and_then(|t| t.find("  ")).map_or(r.len(), |e| e + 1)],
+            ),
+            None => (position + offset_len, rest),
+        };
+        if offset_len > 0 {
+            let offset = u64::from_str_radix(&line[..offset_len], 16).ok();
+            let first_offset = *first_offset.get_or_insert(offset);
+            if let Some(star) = squeeze.take() {
+                let end = offset
+                    .zip(first_offset)
+                    .and_then(|(o, f)| o.checked_sub(f))
+                    .and_then(|n| usize::try_from(n).ok())
+                    .filter(|n| {
+                        *n >= data.len() && *n <= PARSE_DUMP_MAX_SIZE && (*n - data.len()).is_multiple_of(row.len())
+                    })
+                    .ok_or(HexDecodeError::InvalidCharacter { character: '*', position: star })?;
+                while data.len() < end {
+                    data.extend_from_within(row.clone());
+                }
+            }
+        }
+        if rest.trim().is_empty() {
+            continue;
+        }
+        if offset_len == 0 || !rest.starts_with(|c: char| c.is_ascii_whitespace()) {
+            return Err(HexDecodeError::InvalidCharacter {
+                character: rest.chars().next().unwrap_or(' '),
+                position: hex_start,
+            });
+        }
+        let row_start = data.len();
+        data.extend_from_slice(&decode_lenient(rest).map_err(|e| match e {
+            HexDecodeError::InvalidCharacter { character, position } => {
+                HexDecodeError::InvalidCharacter { character, position: hex_start + position }
+            }
+            e => e,
+        })?);
+        row = row_start..data.len();
+    }
+    if let Some(star) = squeeze {
+        return Err(HexDecodeError::InvalidCharacter { character: '*', position: star });
+    }
+    Ok(data)
+}
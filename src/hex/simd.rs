@@ -0,0 +1,385 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ *
+ * (c) ZeroTier, Inc.
+ * https://www.zerotier.com/
+ */
+
+use super::{HEX_CHARS, HEX_CHARS_UPPER};
+
+const fn pair_table(chars: &[u8; 16]) -> [[u8; 2]; 256] {
+    let mut t = [[0_u8; 2]; 256];
+    let mut i = 0;
+    while i < 256 {
+        t[i] = [chars[i >> 4], chars[i & 0xf]];
+        i += 1;
+    }
+    t
+}
+
+const fn nibble_table() -> [u8; 256] {
+    let mut t = [INVALID_NIBBLE; 256];
+    let mut i = 0;
+    while i < 16 {
+        t[HEX_CHARS[i] as usize] = i as u8;
+        t[HEX_CHARS_UPPER[i] as usize] = i as u8;
+        i += 1;
+    }
+    t
+}
+
+const INVALID_NIBBLE: u8 = 0xff;
+
+static PAIRS_LOWER: [[u8; 2]; 256] = pair_table(&HEX_CHARS);
+static PAIRS_UPPER: [[u8; 2]; 256] = pair_table(&HEX_CHARS_UPPER);
+static NIBBLES: [u8; 256] = nibble_table();
+
+const ONES: u64 = 0x0101010101010101;
+const HIGH: u64 = 0x8080808080808080;
+
+/// Encode 'b' into 'out', which must be exactly twice as long, using the fastest available method.
+pub(super) fn encode(b: &[u8], out: &mut [u8], upper: bool) {
+    assert_eq!(out.len(), b.len() * 2);
+    let done = encode_simd(b, out, upper);
+    encode_swar(&b[done..], &mut out[done * 2..], upper);
+}
+
+/// Decode 'hex' into 'out', which must be exactly half as long, returning false on any non-hex character.
+///
+/// The contents of 'out' are unspecified if this returns false.
+pub(super) fn decode(hex: &[u8], out: &mut [u8]) -> bool {
+    assert_eq!(hex.len(), out.len() * 2);
+    match decode_simd(hex, out) {
+        Some(done) => decode_swar(&hex[done * 2..], &mut out[done..]),
+        None => false,
+    }
+}
+
+/// Encode eight bytes at a time by spreading nibbles into a u64, then one byte at a time by table.
+pub(super) fn encode_swar(b: &[u8], out: &mut [u8], upper: bool) {
+    let letter_offset: u64 = if upper {
+        (b'A' - b'0' - 10) as u64
+    } else {
+        (b'a' - b'0' - 10) as u64
+    };
+    let mut chunks = b.chunks_exact(4);
+    let mut out_chunks = out.chunks_exact_mut(8);
+    for (c, o) in (&mut chunks).zip(&mut out_chunks) {
+        let mut v = u32::from_le_bytes([c[0], c[1], c[2], c[3]]) as u64;
+        v = (v | (v << 16)) & 0x0000ffff0000ffff;
+        v = (v | (v << 8)) & 0x00ff00ff00ff00ff;
+        let n = ((v >> 4) & 0x000f000f000f000f) | ((v & 0x000f000f000f000f) << 8);
+        let is_letter = ((n + 0x06 * ONES) >> 4) & ONES;
+        o.copy_from_slice(&(n + 0x30 * ONES + is_letter * letter_offset).to_le_bytes());
+    }
+    let pairs = if upper {
+        &PAIRS_UPPER
+    } else {
+        &PAIRS_LOWER
+    };
+    for (c, o) in chunks
+        .remainder()
+        .iter()
+        .zip(out_chunks.into_remainder().chunks_exact_mut(2))
+    {
+        o.copy_from_slice(&pairs[*c as usize]);
+    }
+}
+
+/// Decode eight hex characters at a time with bytewise range checks in a u64, then two at a time by table.
+pub(super) fn decode_swar(hex: &[u8], out: &mut [u8]) -> bool {
+    // For bytes under 0x80 the high bit of x + (0x80 - k) is set if and only if x >= k.
+    #[inline(always)]
+    fn ge(x: u64, k: u8) -> u64 {
+        (x + (0x80 - k as u64) * ONES) & HIGH
+    }
+
+    let mut chunks = hex.chunks_exact(8);
+    let mut out_chunks = out.chunks_exact_mut(4);
+    for (c, o) in (&mut chunks).zip(&mut out_chunks) {
+        let x = u64::from_le_bytes([c[0], c[1], c[2], c[3], c[4], c[5], c[6], c[7]]);
+        if (x & HIGH) != 0 {
+            return false;
+        }
+        let lower = x | (0x20 * ONES);
+        let is_digit = ge(x, b'0') & !ge(x, b'9' + 1);
+        let is_letter = ge(lower, b'a') & !ge(lower, b'f' + 1);
+        if (is_digit | is_letter) != HIGH {
+            return false;
+        }
+        let n = (x & (0x0f * ONES)) + (is_letter >> 7) * 9;
+        let mut v = ((n & 0x00ff00ff00ff00ff) << 4) | ((n >> 8) & 0x00ff00ff00ff00ff);
+        v = (v | (v >> 8)) & 0x0000ffff0000ffff;
+        v = (v | (v >> 16)) & 0xffffffff;
+        o.copy_from_slice(&(v as u32).to_le_bytes());
+    }
+    let mut invalid = 0;
+    for (c, o) in chunks
+        .remainder()
+        .chunks_exact(2)
+        .zip(out_chunks.into_remainder().iter_mut())
+    {
+        let (hi, lo) = (NIBBLES[c[0] as usize], NIBBLES[c[1] as usize]);
+        invalid |= hi | lo;
+        *o = (hi << 4) | (lo & 0xf);
+    }
+    (invalid & 0xf0) == 0
+}
+
+#[cfg(target_arch = "x86_64")]
+fn encode_simd(b: &[u8], out: &mut [u8], upper: bool) -> usize {
+    unsafe {
+        if std::is_x86_feature_detected!("avx2") {
+            x86::encode_avx2(b, out, upper)
+        } else {
+            x86::encode_sse2(b, out, upper)
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn decode_simd(hex: &[u8], out: &mut [u8]) -> Option<usize> {
+    unsafe {
+        if std::is_x86_feature_detected!("avx2") {
+            x86::decode_avx2(hex, out)
+        } else {
+            x86::decode_sse2(hex, out)
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+fn encode_simd(b: &[u8], out: &mut [u8], upper: bool) -> usize {
+    unsafe { neon::encode_neon(b, out, upper) }
+}
+
+#[cfg(target_arch = "aarch64")]
+fn decode_simd(hex: &[u8], out: &mut [u8]) -> Option<usize> {
+    unsafe { neon::decode_neon(hex, out) }
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+#[inline(always)]
+fn encode_simd(_: &[u8], _: &mut [u8], _: bool) -> usize {
+    0
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+#[inline(always)]
+fn decode_simd(_: &[u8], _: &mut [u8]) -> Option<usize> {
+    Some(0)
+}
+
+/// SSE2 is part of the x86_64 baseline, while AVX2 must be detected at runtime before use.
+///
+/// Encoders return the number of input bytes processed and decoders the number of output bytes
+/// written, or None if a non-hex character was found. Callers finish any remainder.
+#[cfg(target_arch = "x86_64")]
+pub(super) mod x86 {
+    use std::arch::x86_64::*;
+
+    #[inline(always)]
+    unsafe fn nibbles_to_ascii_sse2(n: __m128i, letter_offset: __m128i) -> __m128i {
+        _mm_add_epi8(
+            _mm_add_epi8(n, _mm_set1_epi8(b'0' as i8)),
+            _mm_and_si128(_mm_cmpgt_epi8(n, _mm_set1_epi8(9)), letter_offset),
+        )
+    }
+
+    /// Convert 16 hex characters to nibbles, or None if any are not hex digits.
+    #[inline(always)]
+    unsafe fn ascii_to_nibbles_sse2(c: __m128i) -> Option<__m128i> {
+        // Characters from 0x80 up are negative as signed bytes and fail both range checks.
+        let lower = _mm_or_si128(c, _mm_set1_epi8(0x20));
+        let is_digit = _mm_and_si128(
+            _mm_cmpgt_epi8(c, _mm_set1_epi8((b'0' - 1) as i8)),
+            _mm_cmplt_epi8(c, _mm_set1_epi8((b'9' + 1) as i8)),
+        );
+        let is_letter = _mm_and_si128(
+            _mm_cmpgt_epi8(lower, _mm_set1_epi8((b'a' - 1) as i8)),
+            _mm_cmplt_epi8(lower, _mm_set1_epi8((b'f' + 1) as i8)),
+        );
+        if _mm_movemask_epi8(_mm_or_si128(is_digit, is_letter)) != 0xffff {
+            return None;
+        }
+        Some(_mm_or_si128(
+            _mm_and_si128(is_digit, _mm_sub_epi8(c, _mm_set1_epi8(b'0' as i8))),
+            _mm_and_si128(is_letter, _mm_sub_epi8(lower, _mm_set1_epi8((b'a' - 10) as i8))),
+        ))
+    }
+
+    /// Combine pairs of nibbles in 16-bit lanes into byte values in the low half of each lane.
+    #[inline(always)]
+    unsafe fn pack_nibble_pairs_sse2(n: __m128i) -> __m128i {
+        _mm_or_si128(
+            _mm_and_si128(_mm_slli_epi16(n, 4), _mm_set1_epi16(0x00f0)),
+            _mm_srli_epi16(n, 8),
+        )
+    }
+
+    pub unsafe fn encode_sse2(b: &[u8], out: &mut [u8], upper: bool) -> usize {
+        let n = b.len() & !15;
+        let mask = _mm_set1_epi8(0x0f);
+        let letter_offset = _mm_set1_epi8(if upper {
+            7
+        } else {
+            39
+        });
+        let mut i = 0;
+        while i < n {
+            let v = _mm_loadu_si128(b.as_ptr().add(i).cast());
+            let hi = _mm_and_si128(_mm_srli_epi16(v, 4), mask);
+            let lo = _mm_and_si128(v, mask);
+            let dest = out.as_mut_ptr().add(i * 2);
+            _mm_storeu_si128(
+                dest.cast(),
+                nibbles_to_ascii_sse2(_mm_unpacklo_epi8(hi, lo), letter_offset),
+            );
+            _mm_storeu_si128(
+                dest.add(16).cast(),
+                nibbles_to_ascii_sse2(_mm_unpackhi_epi8(hi, lo), letter_offset),
+            );
+            i += 16;
+        }
+        n
+    }
+
+    pub unsafe fn decode_sse2(hex: &[u8], out: &mut [u8]) -> Option<usize> {
+        let n = out.len() & !15;
+        let mut i = 0;
+        while i < n {
+            let src = hex.as_ptr().add(i * 2);
+            let a = pack_nibble_pairs_sse2(ascii_to_nibbles_sse2(_mm_loadu_si128(src.cast()))?);
+            let b = pack_nibble_pairs_sse2(ascii_to_nibbles_sse2(_mm_loadu_si128(src.add(16).cast()))?);
+            _mm_storeu_si128(out.as_mut_ptr().add(i).cast(), _mm_packus_epi16(a, b));
+            i += 16;
+        }
+        Some(n)
+    }
+
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    unsafe fn nibbles_to_ascii_avx2(n: __m256i, letter_offset: __m256i) -> __m256i {
+        _mm256_add_epi8(
+            _mm256_add_epi8(n, _mm256_set1_epi8(b'0' as i8)),
+            _mm256_and_si256(_mm256_cmpgt_epi8(n, _mm256_set1_epi8(9)), letter_offset),
+        )
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn encode_avx2(b: &[u8], out: &mut [u8], upper: bool) -> usize {
+        let n = b.len() & !31;
+        let mask = _mm256_set1_epi8(0x0f);
+        let letter_offset = _mm256_set1_epi8(if upper {
+            7
+        } else {
+            39
+        });
+        let mut i = 0;
+        while i < n {
+            let v = _mm256_loadu_si256(b.as_ptr().add(i).cast());
+            let hi = _mm256_and_si256(_mm256_srli_epi16(v, 4), mask);
+            let lo = _mm256_and_si256(v, mask);
+            // Unpacking works within 128-bit lanes, so the halves are put back in order afterwards.
+            let a = nibbles_to_ascii_avx2(_mm256_unpacklo_epi8(hi, lo), letter_offset);
+            let c = nibbles_to_ascii_avx2(_mm256_unpackhi_epi8(hi, lo), letter_offset);
+            let dest = out.as_mut_ptr().add(i * 2);
+            _mm256_storeu_si256(dest.cast(), _mm256_permute2x128_si256(a, c, 0x20));
+            _mm256_storeu_si256(dest.add(32).cast(), _mm256_permute2x128_si256(a, c, 0x31));
+            i += 32;
+        }
+        n
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn decode_avx2(hex: &[u8], out: &mut [u8]) -> Option<usize> {
+        let n = out.len() & !15;
+        let mut i = 0;
+        while i < n {
+            let c = _mm256_loadu_si256(hex.as_ptr().add(i * 2).cast());
+            let lower = _mm256_or_si256(c, _mm256_set1_epi8(0x20));
+            let is_digit = _mm256_and_si256(
+                _mm256_cmpgt_epi8(c, _mm256_set1_epi8((b'0' - 1) as i8)),
+                _mm256_cmpgt_epi8(_mm256_set1_epi8((b'9' + 1) as i8), c),
+            );
+            let is_letter = _mm256_and_si256(
+                _mm256_cmpgt_epi8(lower, _mm256_set1_epi8((b'a' - 1) as i8)),
+                _mm256_cmpgt_epi8(_mm256_set1_epi8((b'f' + 1) as i8), lower),
+            );
+            if _mm256_movemask_epi8(_mm256_or_si256(is_digit, is_letter)) != -1 {
+                return None;
+            }
+            let nibbles = _mm256_or_si256(
+                _mm256_and_si256(is_digit, _mm256_sub_epi8(c, _mm256_set1_epi8(b'0' as i8))),
+                _mm256_and_si256(is_letter, _mm256_sub_epi8(lower, _mm256_set1_epi8((b'a' - 10) as i8))),
+            );
+            let bytes = _mm256_or_si256(
+                _mm256_and_si256(_mm256_slli_epi16(nibbles, 4), _mm256_set1_epi16(0x00f0)),
+                _mm256_srli_epi16(nibbles, 8),
+            );
+            // Packing also works within lanes, leaving 8 bytes at the start of each to be gathered.
+            let packed = _mm256_permute4x64_epi64(_mm256_packus_epi16(bytes, bytes), 0x08);
+            _mm_storeu_si128(out.as_mut_ptr().add(i).cast(), _mm256_castsi256_si128(packed));
+            i += 16;
+        }
+        Some(n)
+    }
+}
+
+/// NEON is part of the aarch64 baseline. Conventions are the same as for x86.
+#[cfg(target_arch = "aarch64")]
+pub(super) mod neon {
+    use std::arch::aarch64::*;
+
+    use super::super::{HEX_CHARS, HEX_CHARS_UPPER};
+
+    /// Convert 16 hex characters to nibbles, or None if any are not hex digits.
+    #[inline(always)]
+    unsafe fn ascii_to_nibbles(c: uint8x16_t) -> Option<uint8x16_t> {
+        let digit = vsubq_u8(c, vdupq_n_u8(b'0'));
+        let letter = vsubq_u8(vorrq_u8(c, vdupq_n_u8(0x20)), vdupq_n_u8(b'a'));
+        let is_digit = vcleq_u8(digit, vdupq_n_u8(9));
+        let is_letter = vcleq_u8(letter, vdupq_n_u8(5));
+        if vminvq_u8(vorrq_u8(is_digit, is_letter)) != 0xff {
+            return None;
+        }
+        Some(vbslq_u8(is_digit, digit, vaddq_u8(letter, vdupq_n_u8(10))))
+    }
+
+    pub unsafe fn encode_neon(b: &[u8], out: &mut [u8], upper: bool) -> usize {
+        let n = b.len() & !15;
+        let table = vld1q_u8(
+            if upper {
+                HEX_CHARS_UPPER
+            } else {
+                HEX_CHARS
+            }
+            .as_ptr(),
+        );
+        let mask = vdupq_n_u8(0x0f);
+        let mut i = 0;
+        while i < n {
+            let v = vld1q_u8(b.as_ptr().add(i));
+            let hi = vqtbl1q_u8(table, vshrq_n_u8::<4>(v));
+            let lo = vqtbl1q_u8(table, vandq_u8(v, mask));
+            vst2q_u8(out.as_mut_ptr().add(i * 2), uint8x16x2_t(hi, lo));
+            i += 16;
+        }
+        n
+    }
+
+    pub unsafe fn decode_neon(hex: &[u8], out: &mut [u8]) -> Option<usize> {
+        let n = out.len() & !15;
+        let mut i = 0;
+        while i < n {
+            // De-interleaving load puts high nibble characters in .0 and low ones in .1.
+            let c = vld2q_u8(hex.as_ptr().add(i * 2));
+            let hi = ascii_to_nibbles(c.0)?;
+            let lo = ascii_to_nibbles(c.1)?;
+            vst1q_u8(out.as_mut_ptr().add(i), vorrq_u8(vshlq_n_u8::<4>(hi), lo));
+            i += 16;
+        }
+        Some(n)
+    }
+}
@@ -6,9 +6,10 @@
  * https://www.zerotier.com/
  */
 
-use std::fs::File;
+use std::fs::{File, OpenOptions};
 use std::io::{Read, Write};
 use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 /// Default sanity limit parameter for read_limit() used throughout the service.
 pub const DEFAULT_FILE_IO_READ_LIMIT: usize = 262144;
@@ -41,6 +42,68 @@ pub fn fs_restrict_permissions<P: AsRef<Path>>(path: P) -> bool {
     }
 }
 
+/// Read a whole file, failing with InvalidData instead of truncating it if it's larger than limit.
+pub fn read_all_limit<P: AsRef<Path>>(path: P, limit: usize) -> std::io::Result<Vec<u8>> {
+    let v = read_limit(path, limit.saturating_add(1))?;
+    if v.len() > limit {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("file is larger than the {} byte read limit", limit),
+        ))
+    } else {
+        Ok(v)
+    }
+}
+
+/// Atomically replace a file's contents so a crash never leaves it partially written.
+///
+/// Data is written to a temporary file in the same directory, synced, and renamed over the
+/// destination. On Unix the temporary file is created with mode 0600 so it's visible only to the
+/// service's user, and the directory is then synced so the rename itself is durable. Other platforms
+/// leave permissions to the directory's defaults.
+pub fn fs_write_atomic<P: AsRef<Path>>(path: P, data: &[u8]) -> std::io::Result<()> {
+    static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let path = path.as_ref();
+    let name = path
+        .file_name()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let dir = path
+        .parent()
+        .filter(|d| !d.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(name);
+    tmp_name.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let tmp = dir.join(tmp_name);
+
+    let mut opts = OpenOptions::new();
+    let _ = opts.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        let _ = opts.mode(0o600);
+    }
+    let result = opts.open(&tmp).and_then(|mut f| {
+        f.write_all(data)?;
+        f.sync_all()?;
+        drop(f);
+        std::fs::rename(&tmp, path)
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+        return result;
+    }
+
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
 #[inline]
 pub fn write_all_multi<W: Write>(w: &mut W, s: &[&[u8]]) -> std::io::Result<()> {
     for ss in s {
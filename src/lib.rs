@@ -7,10 +7,13 @@
  */
 
 pub mod arrayvec;
+pub mod base32;
+pub mod base58;
 pub mod base64;
 pub mod blob;
 pub mod buf;
 pub mod cast;
+pub mod datetime;
 pub mod dictionary;
 pub mod error;
 pub mod exitcode;
@@ -23,9 +26,17 @@ pub mod memory;
 pub mod ringbuffer;
 pub mod str;
 pub mod sync;
+pub mod timerwheel;
 pub mod tofrombytes;
+pub mod units;
 pub mod varint;
 
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+use std::sync::atomic::{AtomicBool, AtomicI64};
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+use std::sync::Once;
+
 /// Initial value that should be used for monotonic tick time variables.
 pub const NEVER_HAPPENED_TICKS: i64 = i64::MIN / 2;
 
@@ -41,32 +52,114 @@ pub fn ms_since_epoch() -> i64 {
 /// Get an estimate of the number of CPU cores in the system.
 /// This defaults to 1 if information is not available.
 pub fn parallelism() -> usize {
-    static mut PARALLELISM: usize = 0;
-    let mut p = unsafe { PARALLELISM };
+    static PARALLELISM: AtomicUsize = AtomicUsize::new(0);
+    let mut p = PARALLELISM.load(Ordering::Relaxed);
     // It's perfectly fine if this runs more than once due to concurrent calls as it should always yield the same value.
     if p == 0 {
         p = std::thread::available_parallelism().map(|p| p.get()).unwrap_or(1);
-        unsafe {
-            PARALLELISM = p;
-        }
+        PARALLELISM.store(p, Ordering::Relaxed);
     }
     p
 }
 
+/// Read a clock in nanoseconds with clock_gettime().
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[allow(clippy::unnecessary_cast)] // time_t and c_long are narrower on some 32-bit targets
+#[inline(always)]
+fn clock_ns(clock: libc::clockid_t) -> i64 {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    unsafe { libc::clock_gettime(clock, &mut ts) };
+    (ts.tv_sec as i64) * 1_000_000_000 + (ts.tv_nsec as i64)
+}
+
+/// CLOCK_MONOTONIC at the first call to any of the *_monotonic() functions.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[inline(always)]
+fn monotonic_epoch_ns() -> i64 {
+    static EPOCH: std::sync::OnceLock<i64> = std::sync::OnceLock::new();
+    *EPOCH.get_or_init(|| clock_ns(libc::CLOCK_MONOTONIC))
+}
+
+/// Get nanoseconds since an arbitrary time in the past, guaranteed to monotonically increase within a given process.
+///
+/// All of the *_monotonic() functions share this time base, so their values can be compared after scaling.
+/// On every platform the epoch is the first call to any of them in this process, so values start near
+/// zero and are not comparable across processes or restarts.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[inline]
+pub fn ns_monotonic() -> i64 {
+    let epoch = monotonic_epoch_ns();
+    clock_ns(libc::CLOCK_MONOTONIC) - epoch
+}
+
+/// Get nanoseconds since an arbitrary time in the past, guaranteed to monotonically increase within a given process.
+///
+/// All of the *_monotonic() functions share this time base, so their values can be compared after scaling.
+/// On every platform the epoch is the first call to any of them in this process, so values start near
+/// zero and are not comparable across processes or restarts.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+#[inline]
+pub fn ns_monotonic() -> i64 {
+    static STARTUP_INSTANT: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+    STARTUP_INSTANT
+        .get_or_init(std::time::Instant::now)
+        .elapsed()
+        .as_nanos() as i64
+}
+
+/// Get microseconds since an arbitrary time in the past, guaranteed to monotonically increase within a given process.
+#[inline]
+pub fn us_monotonic() -> i64 {
+    ns_monotonic() / 1_000
+}
+
 /// Get milliseconds since an arbitrary time in the past, guaranteed to monotonically increase within a given process.
 #[inline]
 pub fn ms_monotonic() -> i64 {
-    static STARTUP_INSTANT: std::sync::RwLock<Option<std::time::Instant>> = std::sync::RwLock::new(None);
-    let si = *STARTUP_INSTANT.read().unwrap();
-    if let Some(si) = si {
-        si.elapsed().as_millis() as i64
+    ns_monotonic() / 1_000_000
+}
+
+/// Refresh interval of the background ticker behind ms_monotonic_coarse() on platforms without a
+/// coarse kernel clock. On Linux and Android the resolution is instead the kernel tick, typically 1-4ms.
+pub const COARSE_CLOCK_TICK_MS: u64 = 10;
+
+/// Get ms_monotonic() at reduced resolution for hot paths that can tolerate the value lagging a tick or so.
+///
+/// On Linux and Android this reads CLOCK_MONOTONIC_COARSE, which is served from the vDSO without
+/// reading the hardware clock.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[inline]
+pub fn ms_monotonic_coarse() -> i64 {
+    let epoch = monotonic_epoch_ns();
+    // The coarse clock trails CLOCK_MONOTONIC, so right after the epoch it can read a bit before it.
+    ((clock_ns(libc::CLOCK_MONOTONIC_COARSE) - epoch) / 1_000_000).max(0)
+}
+
+/// Get ms_monotonic() at reduced resolution for hot paths that can tolerate the value lagging a tick or so.
+///
+/// This is a single atomic load of a value refreshed every COARSE_CLOCK_TICK_MS by a background
+/// thread, which is started by the first call. If that thread can't be started this falls back to
+/// ms_monotonic() rather than panicking.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+#[inline]
+pub fn ms_monotonic_coarse() -> i64 {
+    static COARSE_NOW: AtomicI64 = AtomicI64::new(0);
+    static TICKING: AtomicBool = AtomicBool::new(false);
+    static TICKER: Once = Once::new();
+    TICKER.call_once(|| {
+        COARSE_NOW.store(ms_monotonic(), Ordering::Relaxed);
+        let spawned = std::thread::Builder::new()
+            .name("ms_monotonic_coarse".into())
+            .spawn(|| loop {
+                std::thread::sleep(std::time::Duration::from_millis(COARSE_CLOCK_TICK_MS));
+                COARSE_NOW.store(ms_monotonic(), Ordering::Relaxed);
+            });
+        TICKING.store(spawned.is_ok(), Ordering::Relaxed);
+    });
+    if TICKING.load(Ordering::Relaxed) {
+        COARSE_NOW.load(Ordering::Relaxed)
     } else {
-        STARTUP_INSTANT
-            .write()
-            .unwrap()
-            .get_or_insert(std::time::Instant::now())
-            .elapsed()
-            .as_millis() as i64
+        ms_monotonic()
     }
 }
 
@@ -148,7 +241,7 @@ pub extern "C" fn unlikely_branch() {}
 mod tests {
     use std::time::Duration;
 
-    use super::ms_monotonic;
+    use super::{ms_monotonic, ms_monotonic_coarse, ns_monotonic, us_monotonic};
 
     #[test]
     fn monotonic_clock_sanity_check() {
@@ -164,4 +257,31 @@ mod tests {
         assert!((end - start).abs() >= 500);
         assert!((end - start).abs() < 750);
     }
+
+    #[test]
+    fn monotonic_clock_epoch_is_process_relative() {
+        // The epoch is the first *_monotonic() call in this test binary, not boot time.
+        assert!(ms_monotonic() < 60_000);
+    }
+
+    #[test]
+    fn monotonic_clock_units_agree() {
+        let ms = ms_monotonic();
+        let us = us_monotonic();
+        let ns = ns_monotonic();
+        assert!(us / 1000 >= ms);
+        assert!(ns / 1000 >= us);
+        assert!(us / 1000 - ms < 100);
+    }
+
+    #[test]
+    fn coarse_clock_follows_monotonic_clock() {
+        let start = ms_monotonic_coarse();
+        assert!(start >= 0);
+        assert!((ms_monotonic() - start) < 100);
+        std::thread::sleep(Duration::from_millis(100));
+        let end = ms_monotonic_coarse();
+        assert!(end - start >= 90);
+        assert!(end <= ms_monotonic());
+    }
 }
@@ -0,0 +1,404 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ *
+ * (c) ZeroTier, Inc.
+ * https://www.zerotier.com/
+ */
+
+const SLOT_BITS: u32 = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+const SLOT_MASK: u64 = (SLOTS as u64) - 1;
+const LEVELS: usize = 6;
+
+/// Maximum distance in ticks between now and a deadline that can be placed directly.
+/// Deadlines further out than this (about 2.2 years of milliseconds) are parked in the top level
+/// and re-placed as time approaches them.
+pub const TIMER_WHEEL_MAX_SPAN: i64 = 1 << (SLOT_BITS as usize * LEVELS);
+
+/// List index of entries whose deadline was already due when they were (re)scheduled.
+const READY_LIST: usize = LEVELS * SLOTS;
+const LIST_COUNT: usize = READY_LIST + 1;
+const NIL: u32 = u32::MAX;
+
+/// Handle to an entry in a TimerWheel, used to cancel or reschedule it.
+///
+/// Handles are generational, so a handle to an entry that has fired or been cancelled will
+/// never match a newer entry that happens to reuse its storage.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct TimerHandle {
+    index: u32,
+    generation: u32,
+}
+
+struct Entry<T> {
+    value: Option<T>,
+    deadline: i64,
+    generation: u32,
+    list: u32,
+    prev: u32,
+    next: u32,
+}
+
+/// Hierarchical timer wheel keyed on the same i64 millisecond tick space as ms_monotonic().
+///
+/// There are six levels of 64 slots, each level covering 64 times the range of the one below it.
+/// Insert, cancel and reschedule are O(1). Advancing visits only occupied slots thanks to a
+/// per-level occupancy bitmap, and each entry is cascaded down at most once per level.
+pub struct TimerWheel<T> {
+    entries: Vec<Entry<T>>,
+    heads: [u32; LIST_COUNT],
+    occupied: [u64; LEVELS],
+    free: u32,
+    count: usize,
+    elapsed: i64,
+}
+
+#[inline(always)]
+fn slot_range(level: usize) -> i64 {
+    1 << (SLOT_BITS as usize * level)
+}
+
+#[inline(always)]
+fn level_range(level: usize) -> i64 {
+    1 << (SLOT_BITS as usize * (level + 1))
+}
+
+impl<T> TimerWheel<T> {
+    /// Create a new empty timer wheel whose current time is 'now'.
+    pub fn new(now: i64) -> Self {
+        Self {
+            entries: Vec::new(),
+            heads: [NIL; LIST_COUNT],
+            occupied: [0; LEVELS],
+            free: NIL,
+            count: 0,
+            elapsed: now,
+        }
+    }
+
+    /// Get the time the wheel was last advanced to.
+    #[inline(always)]
+    pub fn now(&self) -> i64 {
+        self.elapsed
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Schedule a value to be returned by advance() once time reaches 'deadline'.
+    ///
+    /// A deadline at or before the current time will be returned by the next call to advance().
+    pub fn insert(&mut self, deadline: i64, value: T) -> TimerHandle {
+        let index = if self.free != NIL {
+            let index = self.free;
+            let e = &mut self.entries[index as usize];
+            self.free = e.next;
+            e.value = Some(value);
+            e.deadline = deadline;
+            index
+        } else {
+            let index = self.entries.len() as u32;
+            assert_ne!(index, NIL);
+            self.entries.push(Entry {
+                value: Some(value),
+                deadline,
+                generation: 0,
+                list: NIL,
+                prev: NIL,
+                next: NIL,
+            });
+            index
+        };
+        self.count += 1;
+        self.link(index);
+        TimerHandle { index, generation: self.entries[index as usize].generation }
+    }
+
+    /// Cancel a scheduled entry, returning its value if it had not yet fired.
+    pub fn cancel(&mut self, handle: TimerHandle) -> Option<T> {
+        if self.is_live(handle) {
+            self.unlink(handle.index);
+            Some(self.release(handle.index))
+        } else {
+            None
+        }
+    }
+
+    /// Move a scheduled entry to a new deadline, returning false if it has already fired or been cancelled.
+    pub fn reschedule(&mut self, handle: TimerHandle, deadline: i64) -> bool {
+        if self.is_live(handle) {
+            self.unlink(handle.index);
+            self.entries[handle.index as usize].deadline = deadline;
+            self.link(handle.index);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Get the deadline of a scheduled entry.
+    pub fn deadline(&self, handle: TimerHandle) -> Option<i64> {
+        if self.is_live(handle) {
+            Some(self.entries[handle.index as usize].deadline)
+        } else {
+            None
+        }
+    }
+
+    /// Get the value of a scheduled entry, or None if the handle has fired, been cancelled, or is stale.
+    pub fn get(&self, handle: TimerHandle) -> Option<&T> {
+        if self.is_live(handle) {
+            self.entries[handle.index as usize].value.as_ref()
+        } else {
+            None
+        }
+    }
+
+    /// Get the value of a scheduled entry mutably, or None if the handle has fired, been cancelled, or is stale.
+    pub fn get_mut(&mut self, handle: TimerHandle) -> Option<&mut T> {
+        if self.is_live(handle) {
+            self.entries[handle.index as usize].value.as_mut()
+        } else {
+            None
+        }
+    }
+
+    /// Get the earliest time at which advance() could return anything.
+    ///
+    /// This is exact for deadlines less than 64 ticks away and a lower bound otherwise, so it's
+    /// suitable as a sleep duration for a service loop. None is returned if the wheel is empty.
+    pub fn next_deadline(&self) -> Option<i64> {
+        if self.heads[READY_LIST] != NIL {
+            Some(self.elapsed)
+        } else {
+            self.next_expiration().map(|(_, _, t)| t)
+        }
+    }
+
+    /// Advance the wheel to 'now' and return the values of all entries whose deadline is at or before it.
+    ///
+    /// Values are returned in deadline order, with entries that were already due when scheduled
+    /// first. Time going backwards is ignored.
+    pub fn advance(&mut self, now: i64) -> Vec<T> {
+        let mut expired = Vec::new();
+        self.advance_with(now, |v| expired.push(v));
+        expired
+    }
+
+    /// Advance the wheel to 'now' and call a function with each expired value, without allocating.
+    pub fn advance_with<F: FnMut(T)>(&mut self, now: i64, mut f: F) {
+        self.drain_list(READY_LIST, &mut f);
+        while let Some((level, slot, t)) = self.next_expiration() {
+            if t > now {
+                break;
+            }
+            self.elapsed = t;
+            let list = level * SLOTS + slot;
+            if level == 0 {
+                self.drain_list(list, &mut f);
+            } else {
+                // Cascade entries down now that their slot has been reached. Entries never cascade
+                // back into the list being processed, but anything now due lands in the ready list.
+                while self.heads[list] != NIL {
+                    let index = self.heads[list];
+                    self.unlink(index);
+                    self.link(index);
+                }
+                self.drain_list(READY_LIST, &mut f);
+            }
+        }
+        if now > self.elapsed {
+            self.elapsed = now;
+        }
+    }
+
+    #[inline(always)]
+    fn is_live(&self, handle: TimerHandle) -> bool {
+        self.entries
+            .get(handle.index as usize)
+            .is_some_and(|e| e.generation == handle.generation && e.value.is_some())
+    }
+
+    fn next_expiration(&self) -> Option<(usize, usize, i64)> {
+        let mut best: Option<(usize, usize, i64)> = None;
+        for level in 0..LEVELS {
+            let occupied = self.occupied[level];
+            if occupied != 0 {
+                let now_slot = ((self.elapsed >> (SLOT_BITS as usize * level)) as u64 & SLOT_MASK) as u32;
+                let slot = ((occupied.rotate_right(now_slot).trailing_zeros() + now_slot) as usize) & (SLOTS - 1);
+                let level_start = self.elapsed & !(level_range(level) - 1);
+                let mut t = level_start + (slot as i64) * slot_range(level);
+                if t <= self.elapsed {
+                    t += level_range(level);
+                }
+                if best.is_none_or(|(_, _, bt)| t < bt) {
+                    best = Some((level, slot, t));
+                }
+            }
+        }
+        best
+    }
+
+    fn list_for(&self, deadline: i64) -> usize {
+        if deadline <= self.elapsed {
+            READY_LIST
+        } else {
+            let when = deadline.min(self.elapsed.saturating_add(TIMER_WHEEL_MAX_SPAN - 1));
+            let masked = ((when ^ self.elapsed) as u64) | SLOT_MASK;
+            let level = ((63 - masked.leading_zeros()) / SLOT_BITS).min(LEVELS as u32 - 1) as usize;
+            level * SLOTS + (((when >> (SLOT_BITS as usize * level)) as u64 & SLOT_MASK) as usize)
+        }
+    }
+
+    fn link(&mut self, index: u32) {
+        let list = self.list_for(self.entries[index as usize].deadline);
+        let head = self.heads[list];
+        if head != NIL {
+            self.entries[head as usize].prev = index;
+        } else if list != READY_LIST {
+            self.occupied[list / SLOTS] |= 1 << (list % SLOTS);
+        }
+        let e = &mut self.entries[index as usize];
+        e.list = list as u32;
+        e.prev = NIL;
+        e.next = head;
+        self.heads[list] = index;
+    }
+
+    fn unlink(&mut self, index: u32) {
+        let (list, prev, next) = {
+            let e = &self.entries[index as usize];
+            (e.list as usize, e.prev, e.next)
+        };
+        if prev != NIL {
+            self.entries[prev as usize].next = next;
+        } else {
+            self.heads[list] = next;
+            if next == NIL && list != READY_LIST {
+                self.occupied[list / SLOTS] &= !(1 << (list % SLOTS));
+            }
+        }
+        if next != NIL {
+            self.entries[next as usize].prev = prev;
+        }
+        let e = &mut self.entries[index as usize];
+        e.list = NIL;
+        e.prev = NIL;
+        e.next = NIL;
+    }
+
+    fn release(&mut self, index: u32) -> T {
+        let e = &mut self.entries[index as usize];
+        e.generation = e.generation.wrapping_add(1);
+        e.next = self.free;
+        self.free = index;
+        self.count -= 1;
+        e.value.take().unwrap()
+    }
+
+    fn drain_list<F: FnMut(T)>(&mut self, list: usize, f: &mut F) {
+        // Lists are LIFO, so collect the list tail-first to fire entries in the order they were scheduled.
+        let mut index = self.heads[list];
+        if index == NIL {
+            return;
+        }
+        while self.entries[index as usize].next != NIL {
+            index = self.entries[index as usize].next;
+        }
+        while index != NIL {
+            let prev = self.entries[index as usize].prev;
+            self.unlink(index);
+            f(self.release(index));
+            index = prev;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fires_at_deadline() {
+        let mut w = TimerWheel::new(1000);
+        w.insert(1005, 5);
+        w.insert(1001, 1);
+        w.insert(1000 + 64 * 64 + 3, 3);
+        w.insert(900, 0);
+        assert_eq!(w.len(), 4);
+        assert_eq!(w.advance(1000), vec![0]);
+        assert_eq!(w.advance(1004), vec![1]);
+        assert_eq!(w.advance(1005), vec![5]);
+        assert_eq!(w.advance(1000 + 64 * 64 + 2), Vec::<i32>::new());
+        assert_eq!(w.advance(1000 + 64 * 64 + 3), vec![3]);
+        assert!(w.is_empty());
+        assert_eq!(w.next_deadline(), None);
+    }
+
+    #[test]
+    fn cancel_and_reschedule() {
+        let mut w = TimerWheel::new(0);
+        let a = w.insert(10, "a");
+        let b = w.insert(20, "b");
+        let c = w.insert(30, "c");
+        assert_eq!(w.cancel(b), Some("b"));
+        assert_eq!(w.cancel(b), None);
+        assert!(w.reschedule(c, 5));
+        assert_eq!(w.deadline(c), Some(5));
+        assert_eq!(w.next_deadline(), Some(5));
+        assert_eq!(w.advance(10), vec!["c", "a"]);
+        assert!(!w.reschedule(a, 50));
+        assert_eq!(w.get(a), None);
+
+        // A stale handle must not match a new entry reusing the same storage.
+        let d = w.insert(40, "d");
+        assert_eq!(w.cancel(a), None);
+        assert_eq!(w.get(d), Some(&"d"));
+    }
+
+    #[test]
+    fn matches_reference_ordering() {
+        let mut w = TimerWheel::new(0);
+        let mut expected = Vec::new();
+        let mut x = 0x12345678_u64;
+        for i in 0..5000 {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            let deadline = (x % 5_000_000) as i64;
+            w.insert(deadline, (deadline, i));
+            expected.push((deadline, i));
+        }
+        expected.sort();
+        let mut fired = Vec::new();
+        let mut now = 0;
+        while !w.is_empty() {
+            now += 997;
+            let batch = w.advance(now);
+            assert!(batch.windows(2).all(|p| p[0].0 <= p[1].0));
+            for (deadline, i) in batch {
+                assert!(deadline <= now && deadline > now - 997);
+                fired.push((deadline, i));
+            }
+        }
+        fired.sort();
+        assert_eq!(fired, expected);
+    }
+
+    #[test]
+    fn far_future_deadlines() {
+        let mut w = TimerWheel::new(0);
+        let far = TIMER_WHEEL_MAX_SPAN * 3 + 17;
+        w.insert(far, ());
+        assert!(w.advance(far - 1).is_empty());
+        assert_eq!(w.advance(far).len(), 1);
+    }
+}
@@ -0,0 +1,364 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ *
+ * (c) ZeroTier, Inc.
+ * https://www.zerotier.com/
+ */
+
+use crate::error::InvalidParameterError;
+
+const DURATION_UNITS: [(&str, u128); 6] = [
+    ("w", 604800000),
+    ("d", 86400000),
+    ("h", 3600000),
+    ("m", 60000),
+    ("s", 1000),
+    ("ms", 1),
+];
+
+const SIZE_UNITS_BINARY: [(&str, u128); 6] = [
+    ("EiB", 1 << 60),
+    ("PiB", 1 << 50),
+    ("TiB", 1 << 40),
+    ("GiB", 1 << 30),
+    ("MiB", 1 << 20),
+    ("KiB", 1 << 10),
+];
+
+const SIZE_UNITS_DECIMAL: [(&str, u128); 6] = [
+    ("EB", 1000000000000000000),
+    ("PB", 1000000000000000),
+    ("TB", 1000000000000),
+    ("GB", 1000000000),
+    ("MB", 1000000),
+    ("KB", 1000),
+];
+
+/// Parse a decimal number with an optional fraction from the start of 's'.
+/// Returns the integer part, the fraction digits as an integer and their count, and the rest of the string.
+fn parse_decimal(s: &str) -> Result<(u128, u128, u32, &str), InvalidParameterError> {
+    let int_len = s.bytes().take_while(|c| c.is_ascii_digit()).count();
+    let mut int = 0_u128;
+    for c in s[..int_len].bytes() {
+        int = int
+            .checked_mul(10)
+            .and_then(|i| i.checked_add((c - b'0') as u128))
+            .ok_or(InvalidParameterError("value out of range"))?;
+    }
+    let mut s = &s[int_len..];
+    let (mut frac, mut frac_digits) = (0_u128, 0_u32);
+    if let Some(rest) = s.strip_prefix('.') {
+        let frac_len = rest.bytes().take_while(|c| c.is_ascii_digit()).count();
+        // Anything past 18 digits is below the resolution of any unit and is ignored.
+        for c in rest[..frac_len].bytes().take(18) {
+            frac = frac * 10 + (c - b'0') as u128;
+            frac_digits += 1;
+        }
+        if frac_len == 0 && int_len == 0 {
+            return Err(InvalidParameterError("expected a number"));
+        }
+        s = &rest[frac_len..];
+    } else if int_len == 0 {
+        return Err(InvalidParameterError("expected a number"));
+    }
+    Ok((int, frac, frac_digits, s))
+}
+
+fn scale(int: u128, frac: u128, frac_digits: u32, multiplier: u128) -> Option<u128> {
+    int.checked_mul(multiplier)?
+        .checked_add(frac.checked_mul(multiplier)? / 10_u128.pow(frac_digits))
+}
+
+/// Parse a human readable duration such as "1h30m", "250ms", "1.5s" or "2d 12h" into milliseconds.
+///
+/// Recognized units are w, d, h, m, s and ms. A bare number with no unit is taken to be milliseconds
+/// so that existing configuration values keep their meaning. A leading '-' negates the whole
+/// duration, e.g. "-1h30m" is -5400000.
+pub fn parse_duration_ms(s: &str) -> Result<i64, InvalidParameterError> {
+    let s = s.trim();
+    let (negative, mut s) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest.trim_start()),
+        None => (false, s),
+    };
+    if s.is_empty() {
+        return Err(InvalidParameterError("empty duration"));
+    }
+    let mut total = 0_u128;
+    while !s.is_empty() {
+        let (int, frac, frac_digits, rest) = parse_decimal(s)?;
+        let unit_len = rest.bytes().take_while(|c| c.is_ascii_alphabetic()).count();
+        let unit = &rest[..unit_len];
+        let multiplier = if unit.is_empty() {
+            if total != 0 || !rest.trim().is_empty() {
+                return Err(InvalidParameterError("missing duration unit"));
+            }
+            1
+        } else {
+            DURATION_UNITS
+                .iter()
+                .find(|(u, _)| u.eq_ignore_ascii_case(unit))
+                .ok_or(InvalidParameterError("unrecognized duration unit"))?
+                .1
+        };
+        total = scale(int, frac, frac_digits, multiplier)
+            .and_then(|v| total.checked_add(v))
+            .ok_or(InvalidParameterError("duration out of range"))?;
+        s = rest[unit_len..].trim_start();
+    }
+    i128::try_from(total)
+        .ok()
+        .and_then(|t| {
+            i64::try_from(if negative {
+                -t
+            } else {
+                t
+            })
+            .ok()
+        })
+        .ok_or(InvalidParameterError("duration out of range"))
+}
+
+/// Format a duration in milliseconds in the form accepted by parse_duration_ms(), e.g. "1h30m".
+///
+/// Negative durations are formatted with a leading '-'.
+pub fn format_duration_ms(ms: i64) -> String {
+    let mut s = String::new();
+    if ms < 0 {
+        s.push('-');
+    }
+    let mut remaining = ms.unsigned_abs() as u128;
+    if remaining == 0 {
+        s.push_str("0ms");
+    }
+    for (unit, multiplier) in DURATION_UNITS {
+        if remaining >= multiplier {
+            s.push_str((remaining / multiplier).to_string().as_str());
+            s.push_str(unit);
+            remaining %= multiplier;
+        }
+    }
+    s
+}
+
+/// Parse a human readable byte size such as "256KiB", "1.5MB" or "4096" into a number of bytes.
+///
+/// Binary (KiB, MiB, ...) and decimal (KB, MB, ...) units are recognized, case-insensitively, as
+/// are the short forms K, M, G, T, P and E for decimal units. A bare number or "B" means bytes.
+pub fn parse_size(s: &str) -> Result<u64, InvalidParameterError> {
+    let (int, frac, frac_digits, rest) = parse_decimal(s.trim())?;
+    let unit = rest.trim();
+    let multiplier = if unit.is_empty() || unit.eq_ignore_ascii_case("b") {
+        1
+    } else {
+        SIZE_UNITS_BINARY
+            .iter()
+            .chain(SIZE_UNITS_DECIMAL.iter())
+            .find(|(u, _)| u.eq_ignore_ascii_case(unit) || (u.len() == 2 && u[..1].eq_ignore_ascii_case(unit)))
+            .ok_or(InvalidParameterError("unrecognized size unit"))?
+            .1
+    };
+    scale(int, frac, frac_digits, multiplier)
+        .and_then(|v| u64::try_from(v).ok())
+        .ok_or(InvalidParameterError("size out of range"))
+}
+
+/// Format a byte count in the form accepted by parse_size().
+///
+/// The largest binary unit that represents the value exactly is preferred, then the largest exact
+/// decimal unit, so the result always parses back to the same value.
+pub fn format_size(bytes: u64) -> String {
+    let b = bytes as u128;
+    if b != 0 {
+        for (unit, multiplier) in SIZE_UNITS_BINARY.iter().chain(SIZE_UNITS_DECIMAL.iter()) {
+            if b.is_multiple_of(*multiplier) {
+                return format!("{}{}", b / multiplier, unit);
+            }
+        }
+    }
+    format!("{}B", b)
+}
+
+/// Serde adapter for i64 millisecond durations, for use with #[serde(with = "...")].
+///
+/// Human readable formats get strings like "1h30m" and binary formats get the raw integer. Bare
+/// integers are still accepted from human readable formats.
+pub mod duration_ms {
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(ms: &i64, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(super::format_duration_ms(*ms).as_str())
+        } else {
+            serializer.serialize_i64(*ms)
+        }
+    }
+
+    struct DurationVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for DurationVisitor {
+        type Value = i64;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("duration such as \"1h30m\" or milliseconds")
+        }
+
+        fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            super::parse_duration_ms(v).map_err(|e| E::custom(e.to_string()))
+        }
+
+        fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Self::Value, E> {
+            Ok(v)
+        }
+
+        fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Self::Value, E> {
+            i64::try_from(v).map_err(|_| E::custom("duration out of range"))
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(DurationVisitor)
+        } else {
+            deserializer.deserialize_i64(DurationVisitor)
+        }
+    }
+}
+
+/// Serde adapter for u64 byte sizes, for use with #[serde(with = "...")].
+///
+/// Human readable formats get strings like "256KiB" and binary formats get the raw integer. Bare
+/// integers are still accepted from human readable formats.
+pub mod size {
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(super::format_size(*bytes).as_str())
+        } else {
+            serializer.serialize_u64(*bytes)
+        }
+    }
+
+    struct SizeVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for SizeVisitor {
+        type Value = u64;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("size such as \"256KiB\" or bytes")
+        }
+
+        fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            super::parse_size(v).map_err(|e| E::custom(e.to_string()))
+        }
+
+        fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Self::Value, E> {
+            u64::try_from(v).map_err(|_| E::custom("size out of range"))
+        }
+
+        fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Self::Value, E> {
+            Ok(v)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(SizeVisitor)
+        } else {
+            deserializer.deserialize_u64(SizeVisitor)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration_ms("1h30m").unwrap(), 5400000);
+        assert_eq!(parse_duration_ms("250ms").unwrap(), 250);
+        assert_eq!(parse_duration_ms("250").unwrap(), 250);
+        assert_eq!(parse_duration_ms("1.5s").unwrap(), 1500);
+        assert_eq!(parse_duration_ms(" 2d 12H ").unwrap(), 216000000);
+        assert_eq!(parse_duration_ms(".5m").unwrap(), 30000);
+        assert_eq!(parse_duration_ms("-1h30m").unwrap(), -5400000);
+        assert_eq!(parse_duration_ms(" - 250").unwrap(), -250);
+        for bad in [
+            "",
+            "-",
+            "1h30",
+            "5 fortnights",
+            "h",
+            "1.h.",
+            "99999999999999999999w",
+            "--1s",
+            "1s-1ms",
+        ] {
+            assert!(parse_duration_ms(bad).is_err(), "{}", bad);
+        }
+        assert!(parse_duration_ms("9223372036854775808ms").is_err());
+        assert_eq!(parse_duration_ms("-9223372036854775808ms").unwrap(), i64::MIN);
+        for ms in [
+            0,
+            1,
+            999,
+            1000,
+            5400000,
+            5400001,
+            604800000 * 3 + 7,
+            i64::MAX,
+            -1,
+            -5400250,
+            i64::MIN,
+        ] {
+            assert_eq!(parse_duration_ms(&format_duration_ms(ms)).unwrap(), ms);
+        }
+        assert_eq!(format_duration_ms(-5400250), "-1h30m250ms");
+        assert_eq!(format_duration_ms(5400250), "1h30m250ms");
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("256KiB").unwrap(), 262144);
+        assert_eq!(parse_size("1.5MB").unwrap(), 1500000);
+        assert_eq!(parse_size("1.5 mib").unwrap(), 1572864);
+        assert_eq!(parse_size("4k").unwrap(), 4000);
+        assert_eq!(parse_size("4096").unwrap(), 4096);
+        assert_eq!(parse_size("12B").unwrap(), 12);
+        for bad in ["", "KiB", "1XB", "16EiB"] {
+            assert!(parse_size(bad).is_err(), "{}", bad);
+        }
+        assert_eq!(format_size(crate::io::DEFAULT_FILE_IO_READ_LIMIT as u64), "256KiB");
+        assert_eq!(format_size(1500000), "1500KB");
+        for b in [0, 1, 1023, 1024, 1000, 1500000, 262145, u64::MAX] {
+            assert_eq!(parse_size(&format_size(b)).unwrap(), b);
+        }
+    }
+
+    #[test]
+    fn serde_adapters() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Config {
+            #[serde(with = "duration_ms")]
+            interval: i64,
+            #[serde(with = "size")]
+            limit: u64,
+        }
+        let c = Config { interval: 90000, limit: 262144 };
+        let json = serde_json::to_string(&c).unwrap();
+        assert_eq!(json, "{\"interval\":\"1m30s\",\"limit\":\"256KiB\"}");
+        assert_eq!(serde_json::from_str::<Config>(&json).unwrap(), c);
+        assert_eq!(
+            serde_json::from_str::<Config>("{\"interval\":90000,\"limit\":262144}").unwrap(),
+            c
+        );
+        let c = Config { interval: -90001, limit: 0 };
+        let json = serde_json::to_string(&c).unwrap();
+        assert_eq!(json, "{\"interval\":\"-1m30s1ms\",\"limit\":\"0B\"}");
+        assert_eq!(serde_json::from_str::<Config>(&json).unwrap(), c);
+    }
+}
@@ -8,15 +8,63 @@
 
 use std::io::{Read, Write};
 
+use crate::error::VarintDecodeError;
+
 pub const VARINT_MAX_SIZE_BYTES: usize = 10;
 
+/// Maximum encoded size of a u128 varint.
+pub const VARINT_MAX_SIZE_BYTES_U128: usize = 19;
+
+/// Maximum encoded size of a u32 varint.
+pub const VARINT_MAX_SIZE_BYTES_U32: usize = 5;
+
+/// Maximum encoded size of a u16 varint.
+pub const VARINT_MAX_SIZE_BYTES_U16: usize = 3;
+
+/// Number of bytes encode() will use for a value.
+#[inline(always)]
+pub const fn encoded_len(v: u64) -> usize {
+    if v == 0 {
+        1
+    } else {
+        (64 - v.leading_zeros() as usize).div_ceil(7)
+    }
+}
+
+/// Number of bytes encode_u128() will use for a value.
+#[inline(always)]
+pub const fn encoded_len_u128(v: u128) -> usize {
+    if v == 0 {
+        1
+    } else {
+        (128 - v.leading_zeros() as usize).div_ceil(7)
+    }
+}
+
+/// Number of bytes encode_i64() will use for a value.
+#[inline(always)]
+pub const fn encoded_len_i64(v: i64) -> usize {
+    encoded_len(zigzag(v))
+}
+
+/// Map signed to unsigned so values near zero of either sign stay small: 0, -1, 1, -2 ... become 0, 1, 2, 3 ...
+#[inline(always)]
+const fn zigzag(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+#[inline(always)]
+const fn unzigzag(v: u64) -> i64 {
+    ((v >> 1) as i64) ^ -((v & 1) as i64)
+}
+
 /// Encode an integer as a varint.
 ///
-/// WARNING: if the supplied byte slice does not have at least 10 bytes available this may panic.
-/// This is checked in debug mode by an assertion.
+/// WARNING: if the supplied byte slice does not have at least encoded_len(v) bytes available this
+/// will panic. This is checked in debug mode by an assertion.
 #[inline]
 pub fn encode(b: &mut [u8], mut v: u64) -> usize {
-    debug_assert!(b.len() >= VARINT_MAX_SIZE_BYTES);
+    debug_assert!(b.len() >= encoded_len(v));
     let mut i = 0;
     loop {
         if v > 0x7f {
@@ -44,12 +92,20 @@ pub fn write<W: Write>(w: &mut W, v: u64) -> std::io::Result<()> {
 ///
 /// if the supplied byte slice does not contain a valid varint encoding this will return None.
 /// if the supplied byte slice is shorter than expected this will return None.
+///
+/// This accepts overlong encodings and silently drops bits past 64, so the same value can have
+/// several valid encodings. Use decode_strict() where the encoding must be canonical.
 #[inline]
 pub fn decode(b: &[u8]) -> Option<(u64, usize)> {
+    decode_bounded(b, VARINT_MAX_SIZE_BYTES)
+}
+
+#[inline]
+fn decode_bounded(b: &[u8], max_bytes: usize) -> Option<(u64, usize)> {
     let mut v = 0_u64;
     let mut pos = 0;
     let mut i = 0_usize;
-    while i < b.len() && i < VARINT_MAX_SIZE_BYTES {
+    while i < b.len() && i < max_bytes {
         let b = b[i];
         i += 1;
         if b <= 0x7f {
@@ -64,6 +120,8 @@ pub fn decode(b: &[u8]) -> Option<(u64, usize)> {
 }
 
 /// Read a variable length integer, returning the value and the number of bytes written.
+///
+/// Like decode() this is lenient, see read_strict().
 #[inline]
 pub fn read<R: Read>(r: &mut R) -> std::io::Result<(u64, usize)> {
     let mut v = 0_u64;
@@ -84,8 +142,262 @@ pub fn read<R: Read>(r: &mut R) -> std::io::Result<(u64, usize)> {
     }
 }
 
+/// Read a varint of at most max_bytes, failing with InvalidData if it is longer.
+#[inline]
+fn read_bounded<R: Read>(r: &mut R, max_bytes: usize) -> std::io::Result<(u64, usize)> {
+    let mut b = [0_u8; VARINT_MAX_SIZE_BYTES];
+    for i in 0..max_bytes {
+        r.read_exact(&mut b[i..i + 1])?;
+        if b[i] > 0x7f {
+            return Ok(decode_bounded(&b[..=i], max_bytes).unwrap());
+        }
+    }
+    Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "varint too long"))
+}
+
+#[inline(always)]
+fn out_of_range() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, "varint out of range")
+}
+
+/// Encode a signed integer as a zigzag varint, which can consume up to 10 bytes.
+///
+/// Small negative values are as compact as small positive ones.
+#[inline(always)]
+pub fn encode_i64(b: &mut [u8], v: i64) -> usize {
+    encode(b, zigzag(v))
+}
+
+/// Write a signed integer as a zigzag varint.
+#[inline(always)]
+pub fn write_i64<W: Write>(w: &mut W, v: i64) -> std::io::Result<()> {
+    write(w, zigzag(v))
+}
+
+/// Decode a zigzag varint written by encode_i64().
+#[inline(always)]
+pub fn decode_i64(b: &[u8]) -> Option<(i64, usize)> {
+    decode(b).map(|(v, i)| (unzigzag(v), i))
+}
+
+/// Read a zigzag varint written by write_i64().
+#[inline(always)]
+pub fn read_i64<R: Read>(r: &mut R) -> std::io::Result<(i64, usize)> {
+    read(r).map(|(v, i)| (unzigzag(v), i))
+}
+
+/// Encode a u32 as a varint, which can consume up to 5 bytes.
+///
+/// The encoding is the same as encode() for the same value.
+#[inline(always)]
+pub fn encode_u32(b: &mut [u8], v: u32) -> usize {
+    encode(b, v as u64)
+}
+
+/// Write a u32 as a varint.
+#[inline(always)]
+pub fn write_u32<W: Write>(w: &mut W, v: u32) -> std::io::Result<()> {
+    write(w, v as u64)
+}
+
+/// Decode a u32 varint, returning None if it is longer than 5 bytes or out of range.
+#[inline]
+pub fn decode_u32(b: &[u8]) -> Option<(u32, usize)> {
+    decode_bounded(b, VARINT_MAX_SIZE_BYTES_U32).and_then(|(v, i)| Some((u32::try_from(v).ok()?, i)))
+}
+
+/// Read a u32 varint, failing with InvalidData if it is longer than 5 bytes or out of range.
+#[inline]
+pub fn read_u32<R: Read>(r: &mut R) -> std::io::Result<(u32, usize)> {
+    let (v, i) = read_bounded(r, VARINT_MAX_SIZE_BYTES_U32)?;
+    Ok((u32::try_from(v).map_err(|_| out_of_range())?, i))
+}
+
+/// Encode a u16 as a varint, which can consume up to 3 bytes.
+///
+/// The encoding is the same as encode() for the same value.
+#[inline(always)]
+pub fn encode_u16(b: &mut [u8], v: u16) -> usize {
+    encode(b, v as u64)
+}
+
+/// Write a u16 as a varint.
+#[inline(always)]
+pub fn write_u16<W: Write>(w: &mut W, v: u16) -> std::io::Result<()> {
+    write(w, v as u64)
+}
+
+/// Decode a u16 varint, returning None if it is longer than 3 bytes or out of range.
+#[inline]
+pub fn decode_u16(b: &[u8]) -> Option<(u16, usize)> {
+    decode_bounded(b, VARINT_MAX_SIZE_BYTES_U16).and_then(|(v, i)| Some((u16::try_from(v).ok()?, i)))
+}
+
+/// Read a u16 varint, failing with InvalidData if it is longer than 3 bytes or out of range.
+#[inline]
+pub fn read_u16<R: Read>(r: &mut R) -> std::io::Result<(u16, usize)> {
+    let (v, i) = read_bounded(r, VARINT_MAX_SIZE_BYTES_U16)?;
+    Ok((u16::try_from(v).map_err(|_| out_of_range())?, i))
+}
+
+/// Encode a u128 as a varint, which can consume up to 19 bytes.
+///
+/// WARNING: if the supplied byte slice does not have at least encoded_len_u128(v) bytes available
+/// this will panic. This is checked in debug mode by an assertion.
+#[inline]
+pub fn encode_u128(b: &mut [u8], mut v: u128) -> usize {
+    debug_assert!(b.len() >= encoded_len_u128(v));
+    let mut i = 0;
+    while v > 0x7f {
+        b[i] = (v as u8) & 0x7f;
+        i += 1;
+        v >>= 7;
+    }
+    b[i] = (v as u8) | 0x80;
+    i + 1
+}
+
+/// Write a u128 as a varint.
+#[inline]
+pub fn write_u128<W: Write>(w: &mut W, v: u128) -> std::io::Result<()> {
+    let mut b = [0_u8; VARINT_MAX_SIZE_BYTES_U128];
+    let i = encode_u128(&mut b, v);
+    w.write_all(&b[0..i])
+}
+
+/// Decode up to 19 bytes as a u128 varint.
+#[inline]
+pub fn decode_u128(b: &[u8]) -> Option<(u128, usize)> {
+    let mut v = 0_u128;
+    for (i, b) in b.iter().take(VARINT_MAX_SIZE_BYTES_U128).enumerate() {
+        v |= ((b & 0x7f) as u128).wrapping_shl(7 * i as u32);
+        if *b > 0x7f {
+            return Some((v, i + 1));
+        }
+    }
+    None
+}
+
+/// Read a u128 varint, failing with InvalidData if it is longer than 19 bytes.
+#[inline]
+pub fn read_u128<R: Read>(r: &mut R) -> std::io::Result<(u128, usize)> {
+    let mut b = [0_u8; VARINT_MAX_SIZE_BYTES_U128];
+    for i in 0..VARINT_MAX_SIZE_BYTES_U128 {
+        r.read_exact(&mut b[i..i + 1])?;
+        if b[i] > 0x7f {
+            return Ok(decode_u128(&b[..=i]).unwrap());
+        }
+    }
+    Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "varint too long"))
+}
+
+/// Strictly decode a varint of at most 'bits' significant bits.
+///
+/// The final byte must not be a zero group unless it is the only byte, and no bits may be set
+/// past 'bits'. Running out of input is Incomplete only while a valid encoding is still possible.
+fn decode_strict_bits(b: &[u8], bits: u32) -> Result<(u128, usize), VarintDecodeError> {
+    let max_bytes = bits.div_ceil(7) as usize;
+    let mut v = 0_u128;
+    for (i, c) in b.iter().take(max_bytes).enumerate() {
+        let group = (c & 0x7f) as u128;
+        let shift = 7 * i as u32;
+        if (shift + 7) > bits && (group >> (bits - shift)) != 0 {
+            return Err(VarintDecodeError::Overflow);
+        }
+        v |= group << shift;
+        if *c > 0x7f {
+            return if group == 0 && i > 0 {
+                Err(VarintDecodeError::NonCanonical)
+            } else {
+                Ok((v, i + 1))
+            };
+        }
+    }
+    Err(if b.len() >= max_bytes {
+        VarintDecodeError::Overflow
+    } else {
+        VarintDecodeError::Incomplete
+    })
+}
+
+/// Read a varint byte by byte and strictly decode it, see decode_strict_bits().
+fn read_strict_bits<R: Read>(r: &mut R, bits: u32) -> std::io::Result<(u128, usize)> {
+    let mut b = [0_u8; VARINT_MAX_SIZE_BYTES_U128];
+    let max_bytes = bits.div_ceil(7) as usize;
+    for i in 0..max_bytes {
+        r.read_exact(&mut b[i..i + 1])?;
+        if b[i] > 0x7f {
+            break;
+        }
+    }
+    Ok(decode_strict_bits(&b[..max_bytes], bits)?)
+}
+
+/// Decode a u64 varint, rejecting overlong and overflowing encodings.
+///
+/// Every value has exactly one encoding that this accepts, which is the one encode() produces.
+#[inline]
+pub fn decode_strict(b: &[u8]) -> Result<(u64, usize), VarintDecodeError> {
+    decode_strict_bits(b, u64::BITS).map(|(v, i)| (v as u64, i))
+}
+
+/// Read a u64 varint, rejecting overlong and overflowing encodings with InvalidData.
+#[inline]
+pub fn read_strict<R: Read>(r: &mut R) -> std::io::Result<(u64, usize)> {
+    read_strict_bits(r, u64::BITS).map(|(v, i)| (v as u64, i))
+}
+
+/// Strict counterpart of decode_i64().
+#[inline]
+pub fn decode_i64_strict(b: &[u8]) -> Result<(i64, usize), VarintDecodeError> {
+    decode_strict(b).map(|(v, i)| (unzigzag(v), i))
+}
+
+/// Strict counterpart of read_i64().
+#[inline]
+pub fn read_i64_strict<R: Read>(r: &mut R) -> std::io::Result<(i64, usize)> {
+    read_strict(r).map(|(v, i)| (unzigzag(v), i))
+}
+
+/// Strict counterpart of decode_u32().
+#[inline]
+pub fn decode_u32_strict(b: &[u8]) -> Result<(u32, usize), VarintDecodeError> {
+    decode_strict_bits(b, u32::BITS).map(|(v, i)| (v as u32, i))
+}
+
+/// Strict counterpart of read_u32().
+#[inline]
+pub fn read_u32_strict<R: Read>(r: &mut R) -> std::io::Result<(u32, usize)> {
+    read_strict_bits(r, u32::BITS).map(|(v, i)| (v as u32, i))
+}
+
+/// Strict counterpart of decode_u16().
+#[inline]
+pub fn decode_u16_strict(b: &[u8]) -> Result<(u16, usize), VarintDecodeError> {
+    decode_strict_bits(b, u16::BITS).map(|(v, i)| (v as u16, i))
+}
+
+/// Strict counterpart of read_u16().
+#[inline]
+pub fn read_u16_strict<R: Read>(r: &mut R) -> std::io::Result<(u16, usize)> {
+    read_strict_bits(r, u16::BITS).map(|(v, i)| (v as u16, i))
+}
+
+/// Strict counterpart of decode_u128().
+#[inline]
+pub fn decode_u128_strict(b: &[u8]) -> Result<(u128, usize), VarintDecodeError> {
+    decode_strict_bits(b, u128::BITS)
+}
+
+/// Strict counterpart of read_u128().
+#[inline]
+pub fn read_u128_strict<R: Read>(r: &mut R) -> std::io::Result<(u128, usize)> {
+    read_strict_bits(r, u128::BITS)
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::error::VarintDecodeError;
     use crate::varint::*;
 
     #[test]
@@ -100,4 +412,116 @@ mod tests {
             assert_eq!(ii, decode(&t).unwrap().0);
         }
     }
+
+    #[test]
+    fn varint_types() {
+        let mut b = [0_u8; VARINT_MAX_SIZE_BYTES_U128];
+        for shift in 0..128 {
+            for v in [1_u128 << shift, (1_u128 << shift) - 1, u128::MAX >> shift] {
+                let n = encode_u128(&mut b, v);
+                assert_eq!(n, encoded_len_u128(v));
+                assert_eq!(decode_u128(&b[..n]), Some((v, n)));
+                assert_eq!(read_u128(&mut &b[..n]).unwrap(), (v, n));
+                if let Ok(v) = u64::try_from(v) {
+                    assert_eq!(encoded_len(v), n);
+                    assert_eq!(encode(&mut [0_u8; VARINT_MAX_SIZE_BYTES], v), n);
+                }
+            }
+        }
+        assert_eq!(encoded_len_u128(u128::MAX), VARINT_MAX_SIZE_BYTES_U128);
+        assert_eq!(encoded_len(u64::MAX), VARINT_MAX_SIZE_BYTES);
+        assert_eq!(encoded_len(u32::MAX as u64), VARINT_MAX_SIZE_BYTES_U32);
+        assert_eq!(encoded_len(u16::MAX as u64), VARINT_MAX_SIZE_BYTES_U16);
+        assert!(decode_u128(&[0; VARINT_MAX_SIZE_BYTES_U128 + 1]).is_none());
+
+        for v in [0, 1, -1, 63, -64, 64, -65, i64::MAX, i64::MIN] {
+            let n = encode_i64(&mut b, v);
+            assert_eq!(n, encoded_len_i64(v));
+            assert_eq!(decode_i64(&b[..n]), Some((v, n)));
+            let mut w = Vec::new();
+            write_i64(&mut w, v).unwrap();
+            assert_eq!(read_i64(&mut w.as_slice()).unwrap(), (v, n));
+        }
+        assert_eq!(encoded_len_i64(-64), 1);
+        assert_eq!(encoded_len_i64(-65), 2);
+
+        for v in [0, 1, 0x7f, 0x80, u32::MAX] {
+            let mut w = Vec::new();
+            write_u32(&mut w, v).unwrap();
+            assert_eq!(decode_u32(&w), Some((v, w.len())));
+            assert_eq!(read_u32(&mut w.as_slice()).unwrap(), (v, w.len()));
+            assert_eq!(encode_u32(&mut b, v), w.len());
+        }
+        for v in [0, 1, 0x7f, 0x80, u16::MAX] {
+            let mut w = Vec::new();
+            write_u16(&mut w, v).unwrap();
+            assert_eq!(decode_u16(&w), Some((v, w.len())));
+            assert_eq!(read_u16(&mut w.as_slice()).unwrap(), (v, w.len()));
+            assert_eq!(encode_u16(&mut b, v), w.len());
+        }
+        let mut w = Vec::new();
+        write(&mut w, u32::MAX as u64 + 1).unwrap();
+        assert!(decode_u32(&w).is_none());
+        assert!(read_u32(&mut w.as_slice()).is_err());
+        assert!(decode_u16(&w).is_none());
+        assert!(read_u16(&mut w.as_slice()).is_err());
+    }
+
+    #[test]
+    fn varint_strict() {
+        let mut b = [0_u8; VARINT_MAX_SIZE_BYTES];
+        for v in [0, 1, 0x7f, 0x80, 0x3fff, 0x4000, u32::MAX as u64, 1 << 63, u64::MAX] {
+            let n = encode(&mut b, v);
+            assert_eq!(decode_strict(&b[..n]), Ok((v, n)));
+            assert_eq!(read_strict(&mut &b[..n]).unwrap(), (v, n));
+            assert_eq!(decode_strict(&b[..n - 1]), Err(VarintDecodeError::Incomplete));
+            assert_eq!(
+                read_strict(&mut &b[..n - 1]).unwrap_err().kind(),
+                std::io::ErrorKind::UnexpectedEof
+            );
+        }
+
+        // Overlong: trailing zero groups decode to the same value leniently but not strictly.
+        assert_eq!(decode(&[0x01, 0x80]), Some((1, 2)));
+        assert_eq!(decode_strict(&[0x01, 0x80]), Err(VarintDecodeError::NonCanonical));
+        assert_eq!(decode_strict(&[0x00, 0x00, 0x80]), Err(VarintDecodeError::NonCanonical));
+        assert_eq!(decode_strict(&[0x80]), Ok((0, 1)));
+        assert_eq!(
+            read_strict(&mut [0x01_u8, 0x80].as_slice()).unwrap_err().kind(),
+            std::io::ErrorKind::InvalidData
+        );
+
+        // Overflow: the 10th byte of a u64 can only carry one bit.
+        let mut max = [0x7f_u8; VARINT_MAX_SIZE_BYTES];
+        max[9] = 0x81;
+        assert_eq!(decode_strict(&max), Ok((u64::MAX, 10)));
+        max[9] = 0x82;
+        assert_eq!(decode(&max), Some((u64::MAX >> 1, 10)));
+        assert_eq!(decode_strict(&max), Err(VarintDecodeError::Overflow));
+        assert_eq!(decode_strict(&[0x7f; 11]), Err(VarintDecodeError::Overflow));
+        assert_eq!(decode_strict(&[0x7f; 9]), Err(VarintDecodeError::Incomplete));
+        assert_eq!(
+            read_strict(&mut [0x7f_u8; 11].as_slice()).unwrap_err().kind(),
+            std::io::ErrorKind::InvalidData
+        );
+
+        assert_eq!(decode_u32_strict(&[0x7f, 0x7f, 0x7f, 0x7f, 0x8f]), Ok((u32::MAX, 5)));
+        assert_eq!(
+            decode_u32_strict(&[0x7f, 0x7f, 0x7f, 0x7f, 0x90]),
+            Err(VarintDecodeError::Overflow)
+        );
+        assert_eq!(decode_u16_strict(&[0x7f, 0x7f, 0x83]), Ok((u16::MAX, 3)));
+        assert_eq!(decode_u16_strict(&[0x7f, 0x7f, 0x84]), Err(VarintDecodeError::Overflow));
+        assert_eq!(read_u16_strict(&mut [0x7f_u8, 0xff].as_slice()).unwrap(), (0x3fff, 2));
+        assert_eq!(decode_i64_strict(&[0x81]), Ok((-1, 1)));
+        assert_eq!(read_i64_strict(&mut [0x82_u8].as_slice()).unwrap(), (1, 1));
+        assert_eq!(read_u32_strict(&mut [0x85_u8].as_slice()).unwrap(), (5, 1));
+
+        let mut b = [0_u8; VARINT_MAX_SIZE_BYTES_U128];
+        let n = encode_u128(&mut b, u128::MAX);
+        assert_eq!(decode_u128_strict(&b[..n]), Ok((u128::MAX, n)));
+        assert_eq!(read_u128_strict(&mut &b[..n]).unwrap(), (u128::MAX, n));
+        b[n - 1] += 1;
+        assert_eq!(decode_u128_strict(&b[..n]), Err(VarintDecodeError::Overflow));
+    }
 }